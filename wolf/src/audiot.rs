@@ -0,0 +1,325 @@
+use crate::wolf_asset::{le_u16, Result, WolfAssetError};
+
+use std::fs;
+use std::path::Path;
+
+/// Length and priority that start every PC speaker and AdLib sound
+const SOUND_COMMON_SIZE: usize = 6;
+const INSTRUMENT_SIZE: usize = 16;
+
+/// Where the sections of AUDIOT start, differs between game versions
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AudioLayout {
+    pub num_sounds: usize,
+    pub start_pc_sounds: usize,
+    pub start_adlib_sounds: usize,
+    pub start_digi_sounds: usize,
+    pub start_music: usize,
+    pub num_music: usize,
+}
+
+/// Chunk layout of Wolfenstein 3D 1.4 registered (audiowl6.h)
+pub const WL6_AUDIO_LAYOUT: AudioLayout = AudioLayout {
+    num_sounds: 87,
+    start_pc_sounds: 0,
+    start_adlib_sounds: 87,
+    start_digi_sounds: 174,
+    start_music: 261,
+    num_music: 27,
+};
+
+/// PC speaker sound: one timer divisor byte per 140 Hz tick, 0 is silence
+pub struct PcSound {
+    pub priority: u16,
+    pub data: Vec<u8>,
+}
+
+/// OPL2 register values for the modulator (m_) and carrier (c_) operators
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct AdlibInstrument {
+    pub m_char: u8,
+    pub c_char: u8,
+    pub m_scale: u8,
+    pub c_scale: u8,
+    pub m_attack: u8,
+    pub c_attack: u8,
+    pub m_sus: u8,
+    pub c_sus: u8,
+    pub m_wave: u8,
+    pub c_wave: u8,
+    pub n_conn: u8,
+    pub voice: u8,
+    pub mode: u8,
+}
+
+/// AdLib sound effect: an instrument and one note byte per 140 Hz tick, 0 is silence
+pub struct AdlibSound {
+    pub priority: u16,
+    pub instrument: AdlibInstrument,
+    pub block: u8,
+    pub data: Vec<u8>,
+}
+
+/// An OPL2 register write followed by a delay in 700 Hz ticks
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ImfCommand {
+    pub register: u8,
+    pub value: u8,
+    pub delay: u16,
+}
+
+pub struct ImfMusic {
+    pub commands: Vec<ImfCommand>,
+}
+
+pub struct AudioArchive {
+    offsets: Vec<u32>,
+    data: Vec<u8>,
+    layout: AudioLayout,
+}
+
+impl AudioArchive {
+    pub fn open<P: AsRef<Path>>(head: P, audio: P, layout: AudioLayout) -> Result<Self> {
+        let read = |path: P| {
+            fs::read(path).map_err(|e| WolfAssetError::Io {
+                offset: 0,
+                source: e,
+            })
+        };
+        let head = read(head)?;
+        let audio = read(audio)?;
+        AudioArchive::parse(&head, audio, layout)
+    }
+
+    /// Parses AUDIOHED, a list of u32 offsets into AUDIOT, the last one is the end of AUDIOT
+    pub fn parse(head: &[u8], audio: Vec<u8>, layout: AudioLayout) -> Result<Self> {
+        let offsets = head
+            .chunks_exact(4)
+            .map(|entry| u32::from_le_bytes([entry[0], entry[1], entry[2], entry[3]]))
+            .collect::<Vec<u32>>();
+        for (chunk, pair) in offsets.windows(2).enumerate() {
+            if pair[0] > pair[1] || pair[1] as usize > audio.len() {
+                return Err(WolfAssetError::BadChunkOffset {
+                    chunk,
+                    offset: pair[0] as u64,
+                });
+            }
+        }
+        Ok(AudioArchive {
+            offsets: offsets,
+            data: audio,
+            layout: layout,
+        })
+    }
+
+    pub fn layout(&self) -> AudioLayout {
+        self.layout
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    /// Raw bytes of chunk `index`
+    pub fn chunk(&self, index: usize) -> Result<&[u8]> {
+        if index >= self.chunk_count() {
+            return Err(WolfAssetError::ChunkOutOfRange {
+                index,
+                count: self.chunk_count(),
+            });
+        }
+        Ok(&self.data[self.offsets[index] as usize..self.offsets[index + 1] as usize])
+    }
+
+    /// PC speaker sound `index`, counted from the start of the PC speaker section
+    pub fn pc_sound(&self, index: usize) -> Result<PcSound> {
+        let chunk = self.sound_chunk(self.layout.start_pc_sounds, index)?;
+        let data = self.chunk(chunk)?;
+        let offset = self.offsets[chunk] as u64;
+        let (priority, length) = sound_common(data).map_err(|e| e.relocate(|o| offset + o))?;
+        let sound = data
+            .get(SOUND_COMMON_SIZE..SOUND_COMMON_SIZE + length)
+            .ok_or(WolfAssetError::TruncatedChunk { offset })?;
+        Ok(PcSound {
+            priority: priority,
+            data: sound.to_vec(),
+        })
+    }
+
+    /// AdLib sound `index`, counted from the start of the AdLib section
+    pub fn adlib_sound(&self, index: usize) -> Result<AdlibSound> {
+        let chunk = self.sound_chunk(self.layout.start_adlib_sounds, index)?;
+        let data = self.chunk(chunk)?;
+        let offset = self.offsets[chunk] as u64;
+        let (priority, length) = sound_common(data).map_err(|e| e.relocate(|o| offset + o))?;
+        let notes = SOUND_COMMON_SIZE + INSTRUMENT_SIZE + 1;
+        if data.len() < notes {
+            return Err(WolfAssetError::TruncatedHeader {
+                offset,
+                expected: notes,
+            });
+        }
+        let i = &data[SOUND_COMMON_SIZE..SOUND_COMMON_SIZE + INSTRUMENT_SIZE];
+        let instrument = AdlibInstrument {
+            m_char: i[0],
+            c_char: i[1],
+            m_scale: i[2],
+            c_scale: i[3],
+            m_attack: i[4],
+            c_attack: i[5],
+            m_sus: i[6],
+            c_sus: i[7],
+            m_wave: i[8],
+            c_wave: i[9],
+            n_conn: i[10],
+            voice: i[11],
+            mode: i[12],
+        };
+        let sound = data
+            .get(notes..notes + length)
+            .ok_or(WolfAssetError::TruncatedChunk { offset })?;
+        Ok(AdlibSound {
+            priority: priority,
+            instrument: instrument,
+            block: data[notes - 1],
+            data: sound.to_vec(),
+        })
+    }
+
+    /// IMF music `index`, counted from the start of the music section.
+    /// A track is its length in bytes followed by 4-byte commands
+    pub fn music(&self, index: usize) -> Result<ImfMusic> {
+        if index >= self.layout.num_music {
+            return Err(WolfAssetError::ChunkOutOfRange {
+                index,
+                count: self.layout.num_music,
+            });
+        }
+        let chunk = self.layout.start_music + index;
+        let data = self.chunk(chunk)?;
+        let offset = self.offsets[chunk] as u64;
+        if data.len() < 2 {
+            return Err(WolfAssetError::TruncatedHeader {
+                offset,
+                expected: 2,
+            });
+        }
+        let length = le_u16(data, 0) as usize;
+        let commands = data
+            .get(2..2 + length)
+            .ok_or(WolfAssetError::TruncatedChunk { offset })?;
+        Ok(ImfMusic {
+            commands: commands
+                .chunks_exact(4)
+                .map(|c| ImfCommand {
+                    register: c[0],
+                    value: c[1],
+                    delay: le_u16(c, 2),
+                })
+                .collect(),
+        })
+    }
+
+    fn sound_chunk(&self, start: usize, index: usize) -> Result<usize> {
+        if index >= self.layout.num_sounds {
+            return Err(WolfAssetError::ChunkOutOfRange {
+                index,
+                count: self.layout.num_sounds,
+            });
+        }
+        Ok(start + index)
+    }
+}
+
+/// (priority, length) of a PC speaker or AdLib sound
+fn sound_common(data: &[u8]) -> Result<(u16, usize)> {
+    if data.len() < SOUND_COMMON_SIZE {
+        return Err(WolfAssetError::TruncatedHeader {
+            offset: 0,
+            expected: SOUND_COMMON_SIZE,
+        });
+    }
+    let length = u32::from_le_bytes([data[0], data[1], data[2], data[3]]) as usize;
+    Ok((le_u16(data, 4), length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AudioArchive, AudioLayout, ImfCommand};
+    use crate::wolf_asset::WolfAssetError;
+
+    #[test]
+    fn audio_archive_test() {
+        let layout = AudioLayout {
+            num_sounds: 1,
+            start_pc_sounds: 0,
+            start_adlib_sounds: 1,
+            start_digi_sounds: 2,
+            start_music: 3,
+            num_music: 1,
+        };
+
+        let mut pc = vec![3u8, 0, 0, 0, 5, 0];
+        pc.extend_from_slice(&[40, 0, 41, 0]);
+        let mut adlib = vec![2u8, 0, 0, 0, 9, 0];
+        adlib.extend((1..=16).map(|v| v as u8));
+        adlib.extend_from_slice(&[4, 80, 81, 0]);
+        let digi = b"!ID!".to_vec();
+        let mut music = vec![8u8, 0];
+        music.extend_from_slice(&[0x20, 0x01, 0, 0, 0xB0, 0x32, 7, 1]);
+
+        let mut audio = Vec::new();
+        let mut head = Vec::new();
+        for chunk in &[pc, adlib, digi, music] {
+            head.extend_from_slice(&(audio.len() as u32).to_le_bytes());
+            audio.extend_from_slice(chunk);
+        }
+        head.extend_from_slice(&(audio.len() as u32).to_le_bytes());
+
+        let archive = AudioArchive::parse(&head, audio, layout).unwrap();
+        assert_eq!(archive.chunk_count(), 4);
+
+        let pc = archive.pc_sound(0).unwrap();
+        assert_eq!(pc.priority, 5);
+        assert_eq!(pc.data, vec![40, 0, 41]);
+
+        let adlib = archive.adlib_sound(0).unwrap();
+        assert_eq!(adlib.priority, 9);
+        assert_eq!(adlib.instrument.m_char, 1);
+        assert_eq!(adlib.instrument.c_wave, 10);
+        assert_eq!(adlib.instrument.mode, 13);
+        assert_eq!(adlib.block, 4);
+        assert_eq!(adlib.data, vec![80, 81]);
+
+        let music = archive.music(0).unwrap();
+        assert_eq!(
+            music.commands,
+            vec![
+                ImfCommand {
+                    register: 0x20,
+                    value: 0x01,
+                    delay: 0
+                },
+                ImfCommand {
+                    register: 0xB0,
+                    value: 0x32,
+                    delay: 263
+                }
+            ]
+        );
+
+        assert!(matches!(
+            archive.pc_sound(1),
+            Err(WolfAssetError::ChunkOutOfRange { index: 1, count: 1 })
+        ));
+    }
+
+    #[test]
+    fn bad_audio_head_test() {
+        let head = [0u8, 0, 0, 0, 100, 0, 0, 0];
+        assert!(matches!(
+            AudioArchive::parse(&head, vec![0u8; 10], super::WL6_AUDIO_LAYOUT),
+            Err(WolfAssetError::BadChunkOffset { chunk: 0, .. })
+        ));
+    }
+}
@@ -38,21 +38,54 @@ impl WolfCamera {
 	}
 
 	pub fn rotate(&mut self, delta:f32){
+		let angle = delta * self.rotate_speed;
+		let (sin, cos) = angle.sin_cos();
+		self.dir = normalize(Vec2::new(
+			cos * self.dir.x - sin * self.dir.y,
+			sin * self.dir.x + cos * self.dir.y,
+		));
+	}
 
-		let old = self.get_view_angle();
-		let angle = (delta as f32 * self.rotate_speed);
+	pub fn get_view_angle(&self)->f32{
+		self.dir.y.atan2(self.dir.x)
+	}
 
-		self.dir.x = angle.cos() * self.dir.x - angle.sin() * self.dir.y;
-		self.dir.y = angle.sin() * self.dir.x + angle.cos() * self.dir.y;
+	/// Half the view width at distance 1, perpendicular to `dir` and pointing to the
+	/// right side of the screen
+	pub fn plane(&self)->Vec2<f32>{
+		let half_width = (self.fov.to_radians() / 2f32).tan();
+		Vec2::new(-self.dir.y, self.dir.x) * half_width
+	}
 
-		let invsqrt = (self.dir.x * self.dir.x + self.dir.y * self.dir.y).sqrt();
-		self.dir = self.dir/invsqrt;
+	/// Direction of the ray through `screen_x`, -1 at the left edge of the screen and
+	/// 1 at the right edge. Not normalized: it reaches the plane at distance 1
+	pub fn ray(&self, screen_x:f32)->Vec2<f32>{
+		self.dir + self.plane() * screen_x
+	}
 
-		println!("{:?}", self.dir);
-		let new = self.get_view_angle();
+	/// Distance in pixels from the eye to a screen `width` pixels wide, a wall one
+	/// tile high at distance d is this many pixels high divided by d
+	pub fn focal_length(&self, width:usize)->f32{
+		width as f32 / 2f32 / (self.fov.to_radians() / 2f32).tan()
 	}
+}
 
-	pub fn get_view_angle(&self)->f32{
-		self.dir.y.atan2(self.dir.x)
+#[cfg(test)]
+mod tests {
+	use super::WolfCamera;
+	use crate::math::Vec2;
+
+	#[test]
+	fn camera_plane_test() {
+		let mut cam = WolfCamera::new(Vec2::new(0.0, 0.0), Vec2::new(3.0, 0.0), 90.0);
+		assert_eq!(cam.dir, Vec2::new(1.0, 0.0));
+		let plane = cam.plane();
+		assert!(plane.x.abs() < 1e-6 && (plane.y - 1.0).abs() < 1e-6);
+		assert!((cam.focal_length(320) - 160.0).abs() < 1e-3);
+
+		// a quarter turn keeps the direction normalized
+		cam.rotate_speed = 1.0;
+		cam.rotate(std::f32::consts::FRAC_PI_2);
+		assert!(cam.dir.x.abs() < 1e-6 && (cam.dir.y - 1.0).abs() < 1e-6);
 	}
 }
@@ -8,7 +8,9 @@ pub struct Color {
     pub a: u8,
 }
 use std::cell::{RefCell,RefMut};
-use crate::palette::wolf_palette;
+use crate::palette::{Palette, Shift, WOLF_PALETTE};
+use crate::png::save_png;
+use rayon::prelude::*;
 
 use super::math::{Vec2, Vec3};
 use super::trait_def::{Primitive};
@@ -81,7 +83,11 @@ pub struct Canvas {
     color_component: usize,
     pixel_count: usize,
     buffer_bytes: usize,
-    palette:Vec<(u8,u8,u8,u8)>
+    /// palette of the game data, what palette indices mean
+    palette: Palette,
+    /// `palette` with the current shift applied, pixels are drawn with it
+    display: Palette,
+    shift: Option<Shift>,
 }
 
 impl Canvas {
@@ -93,13 +99,44 @@ impl Canvas {
             color_component: comp,
             pixel_count: res.0 * res.1,
             buffer_bytes: res.0 * res.1 * comp,
-            palette:wolf_palette()
+            palette: WOLF_PALETTE,
+            display: WOLF_PALETTE,
+            shift: None,
         }
     }
 
+    /// (width, height) in pixels
+    pub fn size(&self) -> (usize, usize) {
+        self.size
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Colours used from the next drawn pixel on, e.g. from a mod's .pal file. A
+    /// shift in effect is kept
+    pub fn set_palette(&mut self, palette: &Palette) {
+        self.palette = *palette;
+        self.display = self.shift.map_or(*palette, |shift| shift.apply(palette));
+    }
+
+    /// Tints everything drawn from now on, for flashes and fades. The shifted
+    /// palette is only rebuilt when the shift changes
+    pub fn set_shift(&mut self, shift: Option<Shift>) {
+        if shift != self.shift {
+            self.shift = shift;
+            self.display = shift.map_or(self.palette, |shift| shift.apply(&self.palette));
+        }
+    }
+
+    pub fn shift(&self) -> Option<Shift> {
+        self.shift
+    }
+
     #[inline(always)]
     fn color_lut(&mut self, index:u8)->(u8,u8,u8,u8){
-        self.palette[index as usize]
+        self.display[index as usize]
     }
 
     pub fn set_pixel(&mut self, x: u32, y: u32, color: Color) {
@@ -119,6 +156,39 @@ impl Canvas {
         self.framebuffer[ind + 3] = color.3;
     }
 
+    /// RGBA pixels, row by row
+    pub fn buffer(&self) -> &[u8] {
+        &self.framebuffer
+    }
+
+    pub fn save_png<P: AsRef<std::path::Path>>(&self, path: P) -> std::io::Result<()> {
+        save_png(path, self.size.0 as u32, self.size.1 as u32, &self.framebuffer)
+    }
+
+    /// Calls `draw(row, pixels, palette)` with the RGBA pixels of every row, spread
+    /// over the rayon thread pool when `parallel`
+    pub fn draw_rows<F>(&mut self, parallel: bool, draw: F)
+    where
+        F: Fn(usize, &mut [u8], &[(u8, u8, u8, u8)]) + Sync,
+    {
+        let stride = self.size.0 * self.color_component;
+        let palette = &self.display;
+        if stride == 0 {
+            return;
+        }
+        if parallel {
+            self.framebuffer
+                .par_chunks_mut(stride)
+                .enumerate()
+                .for_each(|(row, pixels)| draw(row, pixels, palette));
+        } else {
+            self.framebuffer
+                .chunks_mut(stride)
+                .enumerate()
+                .for_each(|(row, pixels)| draw(row, pixels, palette));
+        }
+    }
+
     pub fn buffer_as_mut(&mut self) -> &mut [u8] {
         &mut self.framebuffer
     }
@@ -127,11 +197,17 @@ impl Canvas {
         self.framebuffer.fill(0xFF);
     }
 
-    pub fn draw_ceil_and_floor(&mut self){
-        let half = self.buffer_bytes/2;
-        self.framebuffer[0..half].fill(64);
-        self.framebuffer[half..].fill(128);
-        self.framebuffer.iter_mut().skip(3).step_by(4).for_each(|v|*v=255);
+    /// Fills the upper half with the `ceiling` and the lower half with the `floor`
+    /// palette colour
+    pub fn draw_ceil_and_floor(&mut self, ceiling: u8, floor: u8) {
+        let half = self.size.1 / 2 * self.size.0 * self.color_component;
+        let (ceiling, floor) = (self.color_lut(ceiling), self.color_lut(floor));
+        let (upper, lower) = self.framebuffer.split_at_mut(half);
+        for (pixels, (r, g, b, a)) in [(upper, ceiling), (lower, floor)] {
+            for pixel in pixels.chunks_exact_mut(4) {
+                pixel.copy_from_slice(&[r, g, b, a]);
+            }
+        }
     }
 
     pub fn set_wall(&mut self, col:usize, wall_color_index:&[u8]){
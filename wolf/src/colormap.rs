@@ -0,0 +1,118 @@
+//! Palette remap tables for depth cueing. Each light level maps every palette
+//! index to the entry closest to its colour blended towards the fog colour
+use crate::palette::Palette;
+
+/// Number of remap tables, level 0 is full brightness, the last one pure fog
+pub const LIGHT_LEVELS: usize = 32;
+
+/// Identity remap, used when there is no fog
+pub const FULL_BRIGHT: [u8; 256] = {
+    let mut table = [0u8; 256];
+    let mut index = 0;
+    while index < 256 {
+        table[index] = index as u8;
+        index += 1;
+    }
+    table
+};
+
+/// Fog of a level: colours fade towards `color`, to half way at a distance of
+/// ln 2 / `density` tiles
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fog {
+    pub color: (u8, u8, u8),
+    pub density: f32,
+}
+
+pub struct ColorMap {
+    palette: Palette,
+    fog: Fog,
+    tables: Vec<[u8; 256]>,
+}
+
+impl ColorMap {
+    pub fn new(palette: &Palette, fog: Fog) -> Self {
+        let nearest = |(r, g, b): (f32, f32, f32)| {
+            palette
+                .iter()
+                .enumerate()
+                .min_by_key(|(_, &(pr, pg, pb, _))| {
+                    let d = |a: u8, b: f32| (a as f32 - b) * (a as f32 - b);
+                    (d(pr, r) + d(pg, g) + d(pb, b)) as u32
+                })
+                .map_or(0, |(index, _)| index as u8)
+        };
+        let mut tables = vec![FULL_BRIGHT];
+        for level in 1..LIGHT_LEVELS {
+            let amount = level as f32 / (LIGHT_LEVELS - 1) as f32;
+            let blend = |c: u8, f: u8| c as f32 + (f as f32 - c as f32) * amount;
+            let mut table = [0u8; 256];
+            for (entry, &(r, g, b, _)) in table.iter_mut().zip(palette.iter()) {
+                *entry = nearest((
+                    blend(r, fog.color.0),
+                    blend(g, fog.color.1),
+                    blend(b, fog.color.2),
+                ));
+            }
+            tables.push(table);
+        }
+        ColorMap {
+            palette: *palette,
+            fog: fog,
+            tables: tables,
+        }
+    }
+
+    pub fn fog(&self) -> Fog {
+        self.fog
+    }
+
+    pub fn palette(&self) -> &Palette {
+        &self.palette
+    }
+
+    /// Light level of something `distance` tiles away, exponential fog
+    pub fn light(&self, distance: f32) -> usize {
+        let amount = 1.0 - (-self.fog.density * distance.max(0.0)).exp();
+        ((amount * (LIGHT_LEVELS - 1) as f32).round() as usize).min(LIGHT_LEVELS - 1)
+    }
+
+    pub fn table(&self, light: usize) -> &[u8; 256] {
+        &self.tables[light.min(LIGHT_LEVELS - 1)]
+    }
+
+    /// Remap table for something `distance` tiles away
+    pub fn at(&self, distance: f32) -> &[u8; 256] {
+        self.table(self.light(distance))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorMap, Fog, FULL_BRIGHT, LIGHT_LEVELS};
+    use crate::palette::WOLF_PALETTE;
+
+    #[test]
+    fn colormap_test() {
+        let fog = Fog {
+            color: (0, 0, 0),
+            density: 0.1,
+        };
+        let map = ColorMap::new(&WOLF_PALETTE, fog);
+        assert_eq!(map.table(0), &FULL_BRIGHT);
+        assert_eq!(map.light(0.0), 0);
+        assert!(map.light(5.0) < map.light(10.0));
+        assert_eq!(map.light(1000.0), LIGHT_LEVELS - 1);
+
+        // everything is black in the thickest fog, colours only get darker
+        let palette = WOLF_PALETTE;
+        let brightness = |index: u8| {
+            let (r, g, b, _) = palette[index as usize];
+            r as u32 + g as u32 + b as u32
+        };
+        assert!(map.table(LIGHT_LEVELS - 1).iter().all(|&index| brightness(index) == 0));
+        let half = map.table(LIGHT_LEVELS / 2);
+        assert!((0..=255u8).all(|index| brightness(half[index as usize]) <= brightness(index)));
+        assert!(half.iter().any(|&index| brightness(index) > 0));
+    }
+}
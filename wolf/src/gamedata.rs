@@ -0,0 +1,389 @@
+use crate::audiot::{AudioLayout, WL6_AUDIO_LAYOUT};
+use crate::vgagraph::{VgaLayout, WL6_VGA_LAYOUT};
+use crate::wolf_asset::{parse_atlas, Result, WolfAssetError};
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Size of MAPHEAD in every supported data set: the RLEW flag and 100 level offsets
+const MAP_HEAD_SIZE: u64 = 402;
+
+/// Chunk layout of Wolfenstein 3D 1.4 shareware (gfxv_wl1.h), it still has the
+/// help and order screens the registered version dropped
+const WL1_VGA_LAYOUT: VgaLayout = VgaLayout {
+    start_font: 1,
+    num_fonts: 2,
+    start_pics: 3,
+    num_pics: 144,
+};
+
+/// Chunk layout of Spear of Destiny (gfxv_sod.h), the demo has fewer pictures
+const SOD_VGA_LAYOUT: VgaLayout = VgaLayout {
+    start_font: 1,
+    num_fonts: 2,
+    start_pics: 3,
+    num_pics: 147,
+};
+
+/// Chunk layout of Spear of Destiny (audiosod.h), the demo uses the same
+const SOD_AUDIO_LAYOUT: AudioLayout = AudioLayout {
+    num_sounds: 81,
+    start_pc_sounds: 0,
+    start_adlib_sounds: 81,
+    start_digi_sounds: 162,
+    start_music: 243,
+    num_music: 24,
+};
+
+/// Sizes of VGAHEAD and AUDIOHED in the 1.4 releases. They follow from the chunk
+/// counts of a release, so they stay the same when levels or graphics are modded
+const HEADER_SIZES: [(GameVariant, u64, u64); 4] = [
+    (GameVariant::Registered, 450, 1156),
+    (GameVariant::Shareware, 471, 1156),
+    (GameVariant::SpearOfDestiny, 510, 1072),
+    (GameVariant::SpearDemo, 402, 1072),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GameVariant {
+    /// Wolfenstein 3D shareware, *.WL1
+    Shareware,
+    /// Wolfenstein 3D registered, *.WL6
+    Registered,
+    /// Spear of Destiny, *.SOD
+    SpearOfDestiny,
+    /// Spear of Destiny demo, *.SDM
+    SpearDemo,
+}
+
+impl GameVariant {
+    /// In order of preference when several data sets share a directory
+    const ALL: [GameVariant; 4] = [
+        GameVariant::Registered,
+        GameVariant::SpearOfDestiny,
+        GameVariant::Shareware,
+        GameVariant::SpearDemo,
+    ];
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            GameVariant::Shareware => "WL1",
+            GameVariant::Registered => "WL6",
+            GameVariant::SpearOfDestiny => "SOD",
+            GameVariant::SpearDemo => "SDM",
+        }
+    }
+
+    pub fn episodes(&self) -> usize {
+        match self {
+            GameVariant::Registered => 6,
+            _ => 1,
+        }
+    }
+
+    /// Spear of Destiny is a single episode of 18 levels, 2 secret levels and the
+    /// final boss, its demo has the first 2
+    pub fn levels_per_episode(&self) -> usize {
+        match self {
+            GameVariant::Shareware | GameVariant::Registered => 10,
+            GameVariant::SpearOfDestiny => 21,
+            GameVariant::SpearDemo => 2,
+        }
+    }
+
+    pub fn vga_layout(&self) -> VgaLayout {
+        match self {
+            GameVariant::Shareware => WL1_VGA_LAYOUT,
+            GameVariant::Registered => WL6_VGA_LAYOUT,
+            GameVariant::SpearOfDestiny | GameVariant::SpearDemo => SOD_VGA_LAYOUT,
+        }
+    }
+
+    pub fn audio_layout(&self) -> AudioLayout {
+        match self {
+            GameVariant::Shareware | GameVariant::Registered => WL6_AUDIO_LAYOUT,
+            GameVariant::SpearOfDestiny | GameVariant::SpearDemo => SOD_AUDIO_LAYOUT,
+        }
+    }
+
+    /// The variant whose header sizes `files` have, None for unknown sizes
+    fn identify(files: &GameFiles) -> Result<Option<GameVariant>> {
+        let sizes = (file_size(&files.vga_head)?, file_size(&files.audio_head)?);
+        Ok(HEADER_SIZES
+            .iter()
+            .find(|&&(_, vga_head, audio_head)| (vga_head, audio_head) == sizes)
+            .map(|&(variant, _, _)| variant))
+    }
+}
+
+fn file_size(path: &Path) -> Result<u64> {
+    fs::metadata(path)
+        .map(|meta| meta.len())
+        .map_err(|e| WolfAssetError::Io {
+            offset: 0,
+            source: e,
+        })
+}
+
+/// Paths of the files making up a data set
+#[derive(Clone, Debug)]
+pub struct GameFiles {
+    pub vswap: PathBuf,
+    pub map_head: PathBuf,
+    pub game_maps: PathBuf,
+    pub vga_head: PathBuf,
+    pub vga_dict: PathBuf,
+    pub vga_graph: PathBuf,
+    pub audio_head: PathBuf,
+    pub audio_t: PathBuf,
+}
+
+pub struct GameData {
+    pub variant: GameVariant,
+    pub files: GameFiles,
+    /// number of levels present in MAPHEAD
+    pub level_count: usize,
+    pub vga_layout: VgaLayout,
+    pub audio_layout: AudioLayout,
+}
+
+impl GameData {
+    /// Looks for a complete data set in `dir`, file names are matched case-insensitively.
+    ///
+    /// The sizes of VGAHEAD and AUDIOHED identify the variant, so renamed data is
+    /// still recognised. Headers of no known release fall back to the variant of the
+    /// file extension. The headers are then checked against the variant: MAPHEAD
+    /// must have its fixed size and the levels it lists must fit the variant, the
+    /// music count is taken from the size of AUDIOHED
+    pub fn discover<P: AsRef<Path>>(dir: P) -> Result<GameData> {
+        let dir = dir.as_ref();
+        let entries = fs::read_dir(dir)
+            .map_err(|e| WolfAssetError::Io {
+                offset: 0,
+                source: e,
+            })?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .collect::<Vec<PathBuf>>();
+        let find = |name: &str| {
+            entries
+                .iter()
+                .find(|path| {
+                    path.file_name()
+                        .and_then(|file| file.to_str())
+                        .is_some_and(|file| file.eq_ignore_ascii_case(name))
+                })
+                .cloned()
+        };
+
+        for &variant in GameVariant::ALL.iter() {
+            let file = |base: &str| find(&format!("{}.{}", base, variant.extension()));
+            let files = match (
+                file("VSWAP"),
+                file("MAPHEAD"),
+                file("GAMEMAPS"),
+                file("VGAHEAD"),
+                file("VGADICT"),
+                file("VGAGRAPH"),
+                file("AUDIOHED"),
+                file("AUDIOT"),
+            ) {
+                (
+                    Some(vswap),
+                    Some(map_head),
+                    Some(game_maps),
+                    Some(vga_head),
+                    Some(vga_dict),
+                    Some(vga_graph),
+                    Some(audio_head),
+                    Some(audio_t),
+                ) => GameFiles {
+                    vswap,
+                    map_head,
+                    game_maps,
+                    vga_head,
+                    vga_dict,
+                    vga_graph,
+                    audio_head,
+                    audio_t,
+                },
+                _ => continue,
+            };
+            let variant = GameVariant::identify(&files)?.unwrap_or(variant);
+            return GameData::check(variant, files);
+        }
+        Err(WolfAssetError::MissingGameData {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    fn check(variant: GameVariant, files: GameFiles) -> Result<GameData> {
+        let map_head = fs::read(&files.map_head).map_err(|e| WolfAssetError::Io {
+            offset: 0,
+            source: e,
+        })?;
+        if (map_head.len() as u64) < MAP_HEAD_SIZE {
+            return Err(WolfAssetError::TruncatedHeader {
+                offset: 0,
+                expected: MAP_HEAD_SIZE as usize,
+            });
+        }
+        let atlas = parse_atlas(&map_head[..MAP_HEAD_SIZE as usize])?;
+        let level_count = atlas
+            .map_offset
+            .iter()
+            .filter(|&&offset| offset > 0)
+            .count();
+        let max_levels = variant.episodes() * variant.levels_per_episode();
+        if level_count == 0 || level_count > max_levels {
+            return Err(WolfAssetError::LevelOutOfRange {
+                index: level_count,
+                count: max_levels,
+            });
+        }
+
+        // every chunk but the last has an offset, plus the end of the file
+        let mut audio_layout = variant.audio_layout();
+        let audio_chunks = (file_size(&files.audio_head)? / 4).saturating_sub(1) as usize;
+        if audio_chunks < audio_layout.start_music {
+            return Err(WolfAssetError::ChunkOutOfRange {
+                index: audio_layout.start_music,
+                count: audio_chunks,
+            });
+        }
+        audio_layout.num_music = audio_chunks - audio_layout.start_music;
+
+        // the picture table inside VGAGRAPH has the final say on the picture count,
+        // here it is only kept within the chunks that exist
+        let mut vga_layout = variant.vga_layout();
+        let vga_chunks = (file_size(&files.vga_head)? / 3).saturating_sub(1) as usize;
+        if vga_chunks <= vga_layout.start_pics {
+            return Err(WolfAssetError::ChunkOutOfRange {
+                index: vga_layout.start_pics,
+                count: vga_chunks,
+            });
+        }
+        vga_layout.num_pics = vga_layout.num_pics.min(vga_chunks - vga_layout.start_pics);
+
+        Ok(GameData {
+            variant: variant,
+            files: files,
+            level_count: level_count,
+            vga_layout: vga_layout,
+            audio_layout: audio_layout,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{GameData, GameVariant};
+    use crate::wolf_asset::WolfAssetError;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    fn scratch_dir(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("wolf_gamedata_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Writes a data set with `levels` levels, only the headers are meaningful
+    fn write_data_set(
+        dir: &Path,
+        extension: &str,
+        levels: usize,
+        vga_chunks: usize,
+        audio_chunks: usize,
+    ) {
+        let mut map_head = 0xABCDu16.to_le_bytes().to_vec();
+        for level in 0..100 {
+            let offset = if level < levels {
+                8 + level as i32 * 64
+            } else {
+                0
+            };
+            map_head.extend_from_slice(&offset.to_le_bytes());
+        }
+        let files: Vec<(&str, Vec<u8>)> = vec![
+            ("vswap", vec![0u8; 6]),
+            ("MAPHEAD", map_head),
+            ("GAMEMAPS", b"TED5v1.0".to_vec()),
+            ("VGAHEAD", vec![0u8; 3 * (vga_chunks + 1)]),
+            ("VGADICT", vec![0u8; 1024]),
+            ("VGAGRAPH", Vec::new()),
+            ("AUDIOHED", vec![0u8; 4 * (audio_chunks + 1)]),
+            ("AUDIOT", Vec::new()),
+        ];
+        for (name, data) in files {
+            fs::write(dir.join(format!("{}.{}", name, extension)), data).unwrap();
+        }
+    }
+
+    #[test]
+    fn discover_test() {
+        let dir = scratch_dir("discover");
+        write_data_set(&dir, "WL1", 10, 156, 288);
+        let data = GameData::discover(&dir).unwrap();
+        assert_eq!(data.variant, GameVariant::Shareware);
+        assert_eq!(data.level_count, 10);
+        assert_eq!(data.vga_layout.num_pics, 144);
+        assert_eq!(data.audio_layout.num_music, 27);
+        assert!(data.files.vswap.ends_with("vswap.WL1"));
+
+        // registered data wins over shareware in the same directory
+        write_data_set(&dir, "wl6", 60, 149, 288);
+        let data = GameData::discover(&dir).unwrap();
+        assert_eq!(data.variant, GameVariant::Registered);
+        assert_eq!(data.level_count, 60);
+        assert_eq!(data.vga_layout.num_pics, 132);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let dir = scratch_dir("sod");
+        write_data_set(&dir, "SOD", 21, 169, 267);
+        let data = GameData::discover(&dir).unwrap();
+        assert_eq!(data.variant, GameVariant::SpearOfDestiny);
+        assert_eq!(data.audio_layout.start_music, 243);
+        assert_eq!(data.audio_layout.num_music, 24);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn discover_renamed_test() {
+        // registered data renamed to the shareware extension
+        let dir = scratch_dir("renamed");
+        write_data_set(&dir, "WL1", 60, 149, 288);
+        let data = GameData::discover(&dir).unwrap();
+        assert_eq!(data.variant, GameVariant::Registered);
+        assert_eq!(data.vga_layout.num_pics, 132);
+        fs::remove_dir_all(&dir).unwrap();
+
+        // headers of no known release keep the variant of the extension
+        let dir = scratch_dir("modded");
+        write_data_set(&dir, "WL6", 60, 160, 288);
+        let data = GameData::discover(&dir).unwrap();
+        assert_eq!(data.variant, GameVariant::Registered);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn discover_mismatch_test() {
+        let dir = scratch_dir("mismatch");
+        assert!(matches!(
+            GameData::discover(&dir),
+            Err(WolfAssetError::MissingGameData { .. })
+        ));
+
+        // more levels than the demo has
+        write_data_set(&dir, "SDM", 21, 133, 267);
+        assert!(matches!(
+            GameData::discover(&dir),
+            Err(WolfAssetError::LevelOutOfRange {
+                index: 21,
+                count: 2
+            })
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
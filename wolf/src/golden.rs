@@ -0,0 +1,243 @@
+//! Golden image tests: frames are rendered headless and compared with reference
+//! PNGs in `tests/golden`. Set `WOLF_UPDATE_GOLDEN=1` to write new references
+use crate::canvas::Canvas;
+use crate::gamedata::GameVariant;
+use crate::level::Level;
+use crate::resource::TexturePool;
+use crate::texture::{From2DData, Sprite, Texture2D};
+use crate::wolf_asset::WolfLevel;
+
+use std::path::PathBuf;
+
+/// How far a frame may drift from its reference
+#[derive(Copy, Clone, Debug)]
+pub struct Tolerance {
+    /// largest difference of a colour channel that still counts as equal
+    pub channel: u8,
+    /// fraction of pixels allowed to differ by more than `channel`
+    pub pixels: f32,
+}
+
+pub const DEFAULT_TOLERANCE: Tolerance = Tolerance {
+    channel: 8,
+    pixels: 0.002,
+};
+
+pub fn golden_path(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name))
+}
+
+/// Number of pixels differing by more than the tolerance, Err when the images
+/// cannot be compared at all
+pub fn compare(expected: &[u8], actual: &[u8], tolerance: Tolerance) -> Result<usize, String> {
+    if expected.len() != actual.len() {
+        return Err(format!(
+            "size differs: {} bytes expected, {} rendered",
+            expected.len(),
+            actual.len()
+        ));
+    }
+    Ok(expected
+        .chunks_exact(4)
+        .zip(actual.chunks_exact(4))
+        .filter(|(e, a)| {
+            e.iter()
+                .zip(a.iter())
+                .any(|(&e, &a)| (e as i16 - a as i16).unsigned_abs() > tolerance.channel as u16)
+        })
+        .count())
+}
+
+/// True when `WOLF_UPDATE_GOLDEN=1` asks for new references
+fn update_golden() -> bool {
+    std::env::var("WOLF_UPDATE_GOLDEN").as_deref() == Ok("1")
+}
+
+/// Compares `canvas` with the golden image `name`. A missing reference fails like a
+/// mismatch, with `WOLF_UPDATE_GOLDEN=1` the frame is written as the new reference
+/// instead. A failing frame is saved next to it as `<name>.actual.png`
+pub fn assert_golden(canvas: &Canvas, name: &str, tolerance: Tolerance) {
+    let path = golden_path(name);
+    if update_golden() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        canvas.save_png(&path).unwrap();
+        println!("wrote golden image {}", path.display());
+        return;
+    }
+    if !path.exists() {
+        let actual = path.with_extension("actual.png");
+        canvas.save_png(&actual).unwrap();
+        panic!(
+            "{} is missing, see {} and rerun with WOLF_UPDATE_GOLDEN=1 to accept it",
+            path.display(),
+            actual.display()
+        );
+    }
+
+    let expected = match stb_image::image::load(&path) {
+        stb_image::image::LoadResult::ImageU8(image) if image.depth == 4 => image,
+        _ => panic!("{} is not an RGBA image", path.display()),
+    };
+    let (width, height) = canvas.size();
+    let result = if (expected.width, expected.height) != (width, height) {
+        Err(format!(
+            "{}x{} expected, {}x{} rendered",
+            expected.width, expected.height, width, height
+        ))
+    } else {
+        compare(&expected.data, canvas.buffer(), tolerance)
+    };
+    let allowed = (tolerance.pixels * (width * height) as f32) as usize;
+    match result {
+        Ok(differing) if differing <= allowed => {}
+        failure => {
+            let actual = path.with_extension("actual.png");
+            canvas.save_png(&actual).unwrap();
+            panic!(
+                "{} does not match the rendered frame ({:?}, {} pixels allowed), see {}",
+                path.display(),
+                failure,
+                allowed,
+                actual.display()
+            );
+        }
+    }
+}
+
+/// 64x64 page in two colours of a checker pattern
+fn checker(page: usize, dark: u8, light: u8) -> Texture2D<u8> {
+    let data = (0..64 * 64)
+        .map(|i| {
+            let (x, y) = (i % 64, i / 64);
+            // a border marks the texture edges, the page number its orientation
+            if x == 0 || y == 0 || (y == 4 && x >= 4 && x < 4 + 4 * (page % 8)) {
+                0
+            } else if (x / 16 + y / 16) % 2 == 0 {
+                dark
+            } else {
+                light
+            }
+        })
+        .collect::<Vec<u8>>();
+    From2DData::from_data(data, 64, 64, 1)
+}
+
+/// 8 wall pages and the 8 door pages, a lamp sprite as the only static sprite
+pub fn test_textures() -> TexturePool {
+    let walls = (0..16)
+        .map(|page| {
+            let base = 32 + (page as u8 % 8) * 16;
+            checker(page, base + 4, base + 10)
+        })
+        .collect();
+    let mut sprites = (0..6).map(|_| Sprite::empty(64, 64)).collect::<Vec<Sprite>>();
+    let (data, mask): (Vec<u8>, Vec<bool>) = (0..64 * 64i32)
+        .map(|i| {
+            let (x, y) = (i % 64 - 32, i / 64 - 40);
+            (14, x * x + y * y < 20 * 20 || (x.abs() < 3 && y < 0))
+        })
+        .unzip();
+    sprites[5] = Sprite::new(data, mask, 64, 64);
+    TexturePool::from_pages(walls, sprites)
+}
+
+/// A 16x16 room split by a wall with a half open door, a lamp on the near side
+pub fn test_level() -> Level {
+    let header = WolfLevel {
+        width: 16,
+        height: 16,
+        ..Default::default()
+    };
+    let walls = (0..256)
+        .map(|i| match (i % 16, i / 16) {
+            (x, y) if x == 0 || y == 0 || x == 15 || y == 15 => 1,
+            (8, 8) => 90,
+            (8, _) => 2,
+            (12, 4) => 3,
+            _ => 107,
+        })
+        .collect();
+    let mut objects = vec![0; 256];
+    objects[6 * 16 + 5] = 26;
+    let planes = [walls, objects, vec![0; 256]];
+    let mut level = Level::new(&header, planes, GameVariant::Registered);
+    level.door_mut(8, 8).unwrap().open = 0.5;
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        assert_golden, compare, golden_path, test_level, test_textures, update_golden, Tolerance,
+        DEFAULT_TOLERANCE,
+    };
+    use crate::camera::WolfCamera;
+    use crate::canvas::Canvas;
+    use crate::colormap::Fog;
+    use crate::math::Vec2;
+    use crate::render::{Flats, Renderer};
+
+    #[test]
+    fn golden_room_test() {
+        let mut renderer = Renderer::new(test_textures());
+        let camera = WolfCamera::new(Vec2::new(3.5, 8.5), Vec2::new(1.0, -0.25), 60.0);
+        let canvas = renderer.render_frame(&test_level(), &camera, (160, 100)).unwrap();
+        assert_golden(&canvas, "room", DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn golden_flats_test() {
+        let mut renderer = Renderer::new(test_textures());
+        renderer.flats = Some(Flats {
+            floor: 4,
+            ceiling: 6,
+        });
+        let mut level = test_level();
+        level.ceiling = 0xbf;
+        let camera = WolfCamera::new(Vec2::new(3.5, 8.5), Vec2::new(1.0, -0.25), 60.0);
+        let canvas = renderer.render_frame(&level, &camera, (160, 100)).unwrap();
+        assert_golden(&canvas, "room_flats", DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn golden_fog_test() {
+        let mut renderer = Renderer::new(test_textures());
+        let mut level = test_level();
+        level.fog = Some(Fog {
+            color: (40, 0, 0),
+            density: 0.15,
+        });
+        let camera = WolfCamera::new(Vec2::new(3.5, 8.5), Vec2::new(1.0, -0.25), 60.0);
+        let canvas = renderer.render_frame(&level, &camera, (160, 100)).unwrap();
+        assert_golden(&canvas, "room_fog", DEFAULT_TOLERANCE);
+    }
+
+    #[test]
+    fn golden_compare_test() {
+        let a = vec![10u8; 4 * 100];
+        let mut b = a.clone();
+        b[0] = 18;
+        b[4] = 19;
+        let tolerance = Tolerance {
+            channel: 8,
+            pixels: 0.0,
+        };
+        assert_eq!(compare(&a, &b, tolerance), Ok(1));
+        assert!(compare(&a, &b[4..], tolerance).is_err());
+    }
+
+    #[test]
+    fn missing_golden_test() {
+        if update_golden() {
+            return;
+        }
+        let canvas = Canvas::new((4, 4));
+        let result =
+            std::panic::catch_unwind(|| assert_golden(&canvas, "missing", DEFAULT_TOLERANCE));
+        let _ = std::fs::remove_file(golden_path("missing").with_extension("actual.png"));
+        assert!(result.is_err());
+        assert!(!golden_path("missing").exists());
+    }
+}
@@ -0,0 +1,264 @@
+//! The status bar below the view, composed from VGAGRAPH pictures like
+//! DrawPlayScreen and friends in WL_AGENT.C
+use crate::canvas::Canvas;
+use crate::player::{Glance, PlayerStatus};
+use crate::texture::Texture2D;
+use crate::vgagraph::VgaGraph;
+use crate::wolf_asset::Result;
+
+use std::collections::HashMap;
+
+/// Size of the status bar picture, at the bottom of the 320x200 screen
+pub const STATUS_WIDTH: usize = 320;
+pub const STATUS_HEIGHT: usize = 40;
+const SCREEN_WIDTH: usize = 320;
+const SCREEN_HEIGHT: usize = 200;
+
+/// Numbers of the status bar pictures, counted from the first picture chunk
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HudPics {
+    pub status_bar: usize,
+    /// knife, pistol, machine gun and chaingun
+    pub knife: usize,
+    pub no_key: usize,
+    pub gold_key: usize,
+    pub silver_key: usize,
+    /// an empty digit, followed by 0 to 9
+    pub blank: usize,
+    /// 7 rows of health, 3 glances each, then the dead face
+    pub face: usize,
+}
+
+/// Wolfenstein 3D 1.4 (gfxv_wl6.h), chunk numbers less the 3 chunks before the
+/// first picture
+pub const WL6_HUD_PICS: HudPics = HudPics {
+    status_bar: 83,
+    knife: 88,
+    no_key: 92,
+    gold_key: 93,
+    silver_key: 94,
+    blank: 95,
+    face: 106,
+};
+
+/// Health rows of the face pictures before the dead face
+const FACE_ROWS: u32 = 7;
+
+/// Where a number is drawn, in 8 pixel columns and rows of the status bar, and
+/// how many digits it has room for
+struct NumberField {
+    x: usize,
+    y: usize,
+    width: usize,
+}
+
+const FLOOR: NumberField = NumberField { x: 2, y: 16, width: 2 };
+const SCORE: NumberField = NumberField { x: 6, y: 16, width: 6 };
+const LIVES: NumberField = NumberField { x: 14, y: 16, width: 1 };
+const HEALTH: NumberField = NumberField { x: 21, y: 16, width: 3 };
+const AMMO: NumberField = NumberField { x: 27, y: 16, width: 2 };
+const FACE_AT: (usize, usize) = (17 * 8, 4);
+const KEYS_AT: (usize, usize) = (30 * 8, 4);
+const SILVER_KEY_Y: usize = 20;
+const WEAPON_AT: (usize, usize) = (32 * 8, 8);
+
+pub struct Hud {
+    pics: HudPics,
+    pictures: HashMap<usize, Texture2D<u8>>,
+}
+
+impl Hud {
+    pub fn load(vga: &VgaGraph, pics: HudPics) -> Result<Self> {
+        Hud::from_pictures(pics, |index| vga.picture(index))
+    }
+
+    /// Collects every picture the status bar uses through `picture`
+    pub fn from_pictures<F>(pics: HudPics, mut picture: F) -> Result<Self>
+    where
+        F: FnMut(usize) -> Result<Texture2D<u8>>,
+    {
+        let fixed = [pics.status_bar, pics.no_key, pics.gold_key, pics.silver_key];
+        let indices = fixed
+            .iter()
+            .copied()
+            .chain(pics.knife..pics.knife + 4)
+            .chain(pics.blank..pics.blank + 11)
+            .chain(pics.face..=pics.face + 3 * FACE_ROWS as usize);
+        let mut pictures = HashMap::new();
+        for index in indices {
+            pictures.insert(index, picture(index)?);
+        }
+        Ok(Hud {
+            pics: pics,
+            pictures: pictures,
+        })
+    }
+
+    /// Face picture for the health and glance, as in DrawFace
+    pub fn face_pic(&self, status: &PlayerStatus) -> usize {
+        if status.health == 0 {
+            return self.pics.face + 3 * FACE_ROWS as usize;
+        }
+        let row = ((100 - status.health.min(100)) / 16).min(FACE_ROWS - 1) as usize;
+        let glance = match status.face.glance {
+            Glance::Left => 0,
+            Glance::Ahead => 1,
+            Glance::Right => 2,
+        };
+        self.pics.face + 3 * row + glance
+    }
+
+    /// The status bar at its original 320x40, as palette indices
+    pub fn compose(&self, status: &PlayerStatus) -> Vec<u8> {
+        let mut bar = vec![0u8; STATUS_WIDTH * STATUS_HEIGHT];
+        self.blit(&mut bar, self.pics.status_bar, (0, 0));
+        self.number(&mut bar, &FLOOR, status.floor);
+        self.number(&mut bar, &SCORE, status.score);
+        self.number(&mut bar, &LIVES, status.lives);
+        self.number(&mut bar, &HEALTH, status.health);
+        self.number(&mut bar, &AMMO, status.ammo);
+        self.blit(&mut bar, self.face_pic(status), FACE_AT);
+        let key = |has: bool, pic: usize| if has { pic } else { self.pics.no_key };
+        self.blit(&mut bar, key(status.gold_key, self.pics.gold_key), KEYS_AT);
+        self.blit(
+            &mut bar,
+            key(status.silver_key, self.pics.silver_key),
+            (KEYS_AT.0, SILVER_KEY_Y),
+        );
+        self.blit(&mut bar, self.pics.knife + status.weapon.index(), WEAPON_AT);
+        bar
+    }
+
+    /// Right aligned digits padded with blanks, the leading digits of a number too
+    /// long for the field are dropped, as in LatchNumber
+    fn number(&self, bar: &mut [u8], field: &NumberField, value: u32) {
+        let digits = value.to_string().into_bytes();
+        let shown = &digits[digits.len().saturating_sub(field.width)..];
+        let pad = field.width - shown.len();
+        let pics = (0..pad)
+            .map(|_| self.pics.blank)
+            .chain(shown.iter().map(|digit| self.pics.blank + 1 + (digit - b'0') as usize));
+        for (column, pic) in pics.enumerate() {
+            self.blit(bar, pic, ((field.x + column) * 8, field.y));
+        }
+    }
+
+    fn blit(&self, bar: &mut [u8], pic: usize, (x, y): (usize, usize)) {
+        let picture = match self.pictures.get(&pic) {
+            Some(picture) => picture,
+            None => return,
+        };
+        let (width, height) = (picture.width as usize, picture.height as usize);
+        for row in 0..height.min(STATUS_HEIGHT.saturating_sub(y)) {
+            let columns = width.min(STATUS_WIDTH.saturating_sub(x));
+            let target = (y + row) * STATUS_WIDTH + x;
+            bar[target..target + columns]
+                .copy_from_slice(&picture.data[row * width..row * width + columns]);
+        }
+    }
+
+    /// Draws the status bar into the bottom of `canvas`
+    pub fn draw(&self, status: &PlayerStatus, canvas: &mut Canvas) {
+        let bar = self.compose(status);
+        let (left, top, width, height) = status_rect(canvas.size());
+        for row in 0..height {
+            let source_row = (row * STATUS_HEIGHT / height.max(1)).min(STATUS_HEIGHT - 1);
+            for col in 0..width {
+                let source_col = (col * STATUS_WIDTH / width.max(1)).min(STATUS_WIDTH - 1);
+                canvas.set_pixel_by_color_index(
+                    (left + col) as u32,
+                    (top + row) as u32,
+                    bar[source_row * STATUS_WIDTH + source_col],
+                );
+            }
+        }
+    }
+}
+
+/// (left, top, width, height) of the status bar on a screen of `size`. It keeps
+/// its share of the 320x200 screen's height and its aspect, centred horizontally
+/// when the screen is wider than that
+pub fn status_rect(size: (usize, usize)) -> (usize, usize, usize, usize) {
+    let scale = (size.0 as f32 / SCREEN_WIDTH as f32).min(size.1 as f32 / SCREEN_HEIGHT as f32);
+    let width = ((STATUS_WIDTH as f32 * scale) as usize).min(size.0);
+    let height = ((STATUS_HEIGHT as f32 * scale) as usize).min(size.1);
+    ((size.0 - width) / 2, size.1 - height, width, height)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{status_rect, Hud, WL6_HUD_PICS};
+    use crate::canvas::Canvas;
+    use crate::player::{Glance, PlayerStatus, Weapon};
+    use crate::texture::From2DData;
+
+    /// Every picture is filled with its own number, the status bar with 0
+    fn test_hud() -> Hud {
+        Hud::from_pictures(WL6_HUD_PICS, |index| {
+            let (width, height) = match index {
+                83 => (320, 40),
+                88..=91 => (48, 24),
+                92..=94 => (8, 16),
+                95..=105 => (8, 16),
+                _ => (24, 32),
+            };
+            let fill = if index == 83 { 0 } else { index as u8 };
+            Ok(From2DData::from_data(vec![fill; width * height], width as u32, height as u32, 1))
+        })
+        .unwrap()
+    }
+
+    fn at(bar: &[u8], x: usize, y: usize) -> u8 {
+        bar[y * 320 + x]
+    }
+
+    #[test]
+    fn compose_test() {
+        let hud = test_hud();
+        let mut status = PlayerStatus {
+            floor: 3,
+            score: 1234567,
+            health: 45,
+            ammo: 99,
+            silver_key: true,
+            weapon: Weapon::ChainGun,
+            ..Default::default()
+        };
+        status.face.glance = Glance::Right;
+        let bar = hud.compose(&status);
+
+        let digit = |n: usize| 96 + n as u8;
+        // floor " 3", score "234567" with the leading digit dropped, lives "3"
+        assert_eq!((at(&bar, 16, 16), at(&bar, 24, 16)), (95, digit(3)));
+        assert_eq!((at(&bar, 48, 16), at(&bar, 88, 16)), (digit(2), digit(7)));
+        assert_eq!(at(&bar, 112, 16), digit(3));
+        assert_eq!((at(&bar, 168, 16), at(&bar, 176, 16)), (95, digit(4)));
+        assert_eq!((at(&bar, 216, 16), at(&bar, 224, 16)), (digit(9), digit(9)));
+        // health 45 is the fourth row of faces, looking right
+        assert_eq!(at(&bar, 136, 4), 106 + 3 * 3 + 2);
+        assert_eq!((at(&bar, 240, 4), at(&bar, 240, 20)), (92, 94));
+        assert_eq!(at(&bar, 256, 8), 91);
+        assert_eq!(at(&bar, 0, 0), 0);
+
+        status.health = 0;
+        assert_eq!(hud.face_pic(&status), 127);
+        status.health = 100;
+        status.face.glance = Glance::Left;
+        assert_eq!(hud.face_pic(&status), 106);
+    }
+
+    #[test]
+    fn status_rect_test() {
+        assert_eq!(status_rect((320, 200)), (0, 160, 320, 40));
+        assert_eq!(status_rect((640, 400)), (0, 320, 640, 80));
+        // a wide screen keeps the bar's aspect and centres it
+        assert_eq!(status_rect((1920, 1080)), (96, 864, 1728, 216));
+
+        let mut canvas = Canvas::new((640, 400));
+        test_hud().draw(&PlayerStatus::default(), &mut canvas);
+        let pixel = |x: usize, y: usize| canvas.buffer()[(y * 640 + x) * 4..(y * 640 + x) * 4 + 4].to_vec();
+        // the pistol picture, scaled twice
+        assert_eq!(pixel(512, 336), pixel(606, 350));
+        assert_ne!(pixel(512, 336), pixel(0, 336));
+    }
+}
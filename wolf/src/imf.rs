@@ -0,0 +1,141 @@
+use crate::audiot::ImfMusic;
+use crate::opl::Opl2;
+use crate::wav::save_wav_i16;
+
+use std::path::Path;
+
+/// Wolfenstein 3D plays IMF music at 700 ticks per second
+pub const IMF_TICK_RATE: u32 = 700;
+
+/// Steps the register writes of an IMF track through an `Opl2`
+pub struct ImfPlayer<'a> {
+    music: &'a ImfMusic,
+    opl: Opl2,
+    position: usize, // next command
+    wait: u32,       // ticks until the next command
+    tick: u64,
+    tick_samples_left: u32,
+}
+
+impl<'a> ImfPlayer<'a> {
+    pub fn new(music: &'a ImfMusic, sample_rate: u32) -> Self {
+        ImfPlayer {
+            music: music,
+            opl: Opl2::new(sample_rate),
+            position: 0,
+            wait: 0,
+            tick: 0,
+            tick_samples_left: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.music.commands.len() && self.wait == 0 && self.tick_samples_left == 0
+    }
+
+    /// Renders up to `out.len()` samples, returns how many were written.
+    /// Less than `out.len()` means the track ended
+    pub fn render(&mut self, out: &mut [i16]) -> usize {
+        let mut written = 0;
+        while written < out.len() {
+            if self.tick_samples_left == 0 && !self.next_tick() {
+                break;
+            }
+            let count = (self.tick_samples_left as usize).min(out.len() - written);
+            self.opl.generate(&mut out[written..written + count]);
+            written += count;
+            self.tick_samples_left -= count as u32;
+        }
+        written
+    }
+
+    /// Runs the commands due at this tick, false once the track is over
+    fn next_tick(&mut self) -> bool {
+        while self.wait == 0 {
+            match self.music.commands.get(self.position) {
+                Some(command) => {
+                    self.opl.write(command.register, command.value);
+                    self.wait = command.delay as u32;
+                    self.position += 1;
+                }
+                None => return false,
+            }
+        }
+        self.wait -= 1;
+        // spread the rounding so that n ticks always last n / 700 seconds
+        let rate = self.opl.sample_rate() as u64;
+        let start = self.tick * rate / IMF_TICK_RATE as u64;
+        self.tick += 1;
+        let end = self.tick * rate / IMF_TICK_RATE as u64;
+        self.tick_samples_left = (end - start) as u32;
+        true
+    }
+}
+
+/// Renders a whole track as signed 16-bit mono samples
+pub fn render_imf(music: &ImfMusic, sample_rate: u32) -> Vec<i16> {
+    let mut player = ImfPlayer::new(music, sample_rate);
+    let mut samples = Vec::new();
+    let mut buffer = vec![0i16; 4096];
+    loop {
+        let written = player.render(&mut buffer);
+        samples.extend_from_slice(&buffer[..written]);
+        if written < buffer.len() {
+            break;
+        }
+    }
+    samples
+}
+
+pub fn save_imf_wav<P: AsRef<Path>>(
+    music: &ImfMusic,
+    sample_rate: u32,
+    path: P,
+) -> std::io::Result<()> {
+    save_wav_i16(path, &render_imf(music, sample_rate), sample_rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_imf, ImfPlayer};
+    use crate::audiot::{ImfCommand, ImfMusic};
+
+    fn command(register: u8, value: u8, delay: u16) -> ImfCommand {
+        ImfCommand {
+            register,
+            value,
+            delay,
+        }
+    }
+
+    #[test]
+    fn imf_render_test() {
+        let music = ImfMusic {
+            commands: vec![
+                command(0x20, 0x21, 0),
+                command(0x40, 0x3F, 0),
+                command(0x23, 0x21, 0),
+                command(0x43, 0x00, 0),
+                command(0x63, 0xF0, 0),
+                command(0x83, 0x0F, 0),
+                command(0xA0, 0x44, 0),
+                // key on for a tenth of a second
+                command(0xB0, 0x32, 70),
+                command(0xB0, 0x12, 35),
+            ],
+        };
+        let samples = render_imf(&music, 7000);
+        assert_eq!(samples.len(), 1050);
+        assert!(samples[..700].iter().any(|&s| s != 0));
+
+        // rendering in small pieces gives the same result
+        let mut player = ImfPlayer::new(&music, 7000);
+        let mut pieces = Vec::new();
+        let mut buffer = [0i16; 33];
+        while !player.is_finished() {
+            let written = player.render(&mut buffer);
+            pieces.extend_from_slice(&buffer[..written]);
+        }
+        assert_eq!(pieces, samples);
+    }
+}
@@ -0,0 +1,463 @@
+use crate::colormap::Fog;
+use crate::gamedata::GameVariant;
+use crate::mapobject::{parse_objects, Direction, Key, MapObject, ObjectKind};
+use crate::wolf_asset::{WolfLevel, MAP_PLANE};
+
+use std::collections::HashMap;
+
+/// Wall tiles are 1..=63, each has a light and a dark VSWAP page
+const LAST_WALL_TILE: u16 = 63;
+/// The wall with the elevator switch texture, using it ends the level
+const ELEVATOR_TILE: u16 = 21;
+/// Doors take tiles 90..=101 in vertical/horizontal pairs: plain, gold, silver,
+/// two unused locks and the elevator door
+const DOOR_TILE: u16 = 90;
+const LAST_DOOR_TILE: u16 = 101;
+const AMBUSH_TILE: u16 = 106;
+/// Floor tiles from here on carry the area number, used for sound propagation
+const AREA_TILE: u16 = 107;
+/// Sub-tile units a pushwall moves in per tile
+pub const PUSHWALL_UNITS: u32 = 64;
+/// How far a pushwall slides, in tiles
+const PUSHWALL_TILES: u32 = 2;
+/// Palette index of the floor, the same on every level
+pub const FLOOR_COLOR: u8 = 0x19;
+/// Palette index of the ceiling per level (vgaCeiling in WL_DRAW.C), ten levels
+/// per episode
+const WL6_CEILING: [u8; 60] = [
+    0x1d, 0x1d, 0x1d, 0x1d, 0x1d, 0x1d, 0x1d, 0x1d, 0x1d, 0xbf, //
+    0x4e, 0x4e, 0x4e, 0x1d, 0x8d, 0x4e, 0x1d, 0x2d, 0x1d, 0x8d, //
+    0x1d, 0x1d, 0x1d, 0x1d, 0x1d, 0x2d, 0xdd, 0x1d, 0x1d, 0x98, //
+    0x1d, 0x9d, 0x2d, 0xdd, 0xdd, 0x9d, 0x2d, 0x4d, 0x1d, 0xdd, //
+    0x7d, 0x1d, 0x2d, 0x2d, 0xdd, 0xd7, 0x1d, 0x1d, 0x1d, 0x2d, //
+    0x1d, 0x1d, 0x1d, 0x1d, 0xdd, 0xdd, 0x7d, 0xdd, 0xdd, 0xdd, //
+];
+const SOD_CEILING: [u8; 21] = [
+    0x6f, 0x4f, 0x1d, 0xde, 0xdf, 0x2e, 0x7f, 0x9e, 0xae, 0x7f, //
+    0x1d, 0xde, 0xdf, 0xde, 0xdf, 0xde, 0xe1, 0xdc, 0x2e, 0x1d, 0xdc,
+];
+
+/// Ceiling colour of a level as in the original, episode and level count from 1
+pub fn ceiling_color(variant: GameVariant, episode: u32, level: u32) -> u8 {
+    let (table, index) = match variant {
+        GameVariant::Shareware | GameVariant::Registered => {
+            (&WL6_CEILING[..], episode.saturating_sub(1) * 10 + level.saturating_sub(1))
+        }
+        GameVariant::SpearOfDestiny | GameVariant::SpearDemo => {
+            (&SOD_CEILING[..], level.saturating_sub(1))
+        }
+    };
+    table.get(index as usize).copied().unwrap_or(table[0])
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum DoorKind {
+    Normal,
+    /// Opens only with the key, the two unused locks of the original have no key
+    Locked(Option<Key>),
+    Elevator,
+}
+
+/// What a wall plane tile is
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Tile {
+    /// `light` is drawn on faces looking north or south, `dark` on east or west faces
+    Wall { light: u16, dark: u16 },
+    /// Same as a wall, pressing it finishes the level
+    ElevatorSwitch { light: u16, dark: u16 },
+    /// A vertical door slides along the y axis and is entered from east or west
+    Door { vertical: bool, kind: DoorKind },
+    Floor { area: u16 },
+    /// Floor tile where standing enemies do not react to noise
+    Ambush,
+    Unknown(u16),
+}
+
+impl Tile {
+    pub fn from_tile(tile: u16) -> Tile {
+        match tile {
+            1..=LAST_WALL_TILE => {
+                let light = (tile - 1) * 2;
+                if tile == ELEVATOR_TILE {
+                    Tile::ElevatorSwitch {
+                        light: light,
+                        dark: light + 1,
+                    }
+                } else {
+                    Tile::Wall {
+                        light: light,
+                        dark: light + 1,
+                    }
+                }
+            }
+            DOOR_TILE..=LAST_DOOR_TILE => {
+                // doors come in pairs, vertical first
+                let (door, side) = ((tile - DOOR_TILE) / 2, (tile - DOOR_TILE) % 2);
+                Tile::Door {
+                    vertical: side == 0,
+                    kind: match door {
+                        0 => DoorKind::Normal,
+                        1 => DoorKind::Locked(Some(Key::Gold)),
+                        2 => DoorKind::Locked(Some(Key::Silver)),
+                        3 | 4 => DoorKind::Locked(None),
+                        _ => DoorKind::Elevator,
+                    },
+                }
+            }
+            AMBUSH_TILE => Tile::Ambush,
+            AREA_TILE..=u16::MAX => Tile::Floor {
+                area: tile - AREA_TILE,
+            },
+            _ => Tile::Unknown(tile),
+        }
+    }
+
+    /// Walls stop rays and movement, doors are handled separately
+    pub fn is_solid(&self) -> bool {
+        matches!(self, Tile::Wall { .. } | Tile::ElevatorSwitch { .. })
+    }
+}
+
+/// State of a door tile
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Door {
+    pub x: i32,
+    pub y: i32,
+    pub vertical: bool,
+    pub kind: DoorKind,
+    /// How far the door has slid into the wall, 0 is closed and 1 fully open
+    pub open: f32,
+}
+
+/// A secret wall sliding away from the tile it was pushed from
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pushwall {
+    pub x: i32,
+    pub y: i32,
+    pub dx: i32,
+    pub dy: i32,
+    /// wall plane value of the moving block
+    pub tile: u16,
+    /// distance moved in `PUSHWALL_UNITS` per tile
+    pub offset: u32,
+}
+
+impl Pushwall {
+    /// Top left corner of the block, in tiles
+    pub fn corner(&self) -> (f32, f32) {
+        let moved = self.offset as f32 / PUSHWALL_UNITS as f32;
+        (
+            self.x as f32 + self.dx as f32 * moved,
+            self.y as f32 + self.dy as f32 * moved,
+        )
+    }
+}
+
+/// A level with its three planes: walls, objects and the third plane which the
+/// game leaves unused
+pub struct Level {
+    pub name: String,
+    pub width: usize,
+    pub height: usize,
+    pub walls: Vec<u16>,
+    pub objects: Vec<MapObject>,
+    pub extra: Vec<u16>,
+    pub doors: Vec<Door>,
+    door_index: HashMap<(i32, i32), usize>,
+    /// Only one secret wall moves at a time, as in the original
+    pub pushwall: Option<Pushwall>,
+    /// Palette index the ceiling is filled with
+    pub ceiling: u8,
+    /// Depth cueing, the original draws everything at full brightness
+    pub fog: Option<Fog>,
+    /// The game the level comes from, its objects and sprites are numbered for it
+    pub variant: GameVariant,
+}
+
+impl Level {
+    /// The objects are decoded as `variant` places them, the ceiling is left at the
+    /// colour of the first level
+    pub fn new(
+        header: &WolfLevel,
+        planes: [Vec<u16>; MAP_PLANE],
+        variant: GameVariant,
+    ) -> Level {
+        let [walls, objects, extra] = planes;
+        let width = header.width.max(0) as usize;
+        let name_length = header
+            .name
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(header.name.len());
+        let doors = walls
+            .iter()
+            .enumerate()
+            .filter_map(|(index, &tile)| match Tile::from_tile(tile) {
+                Tile::Door { vertical, kind } => Some(Door {
+                    x: (index % width.max(1)) as i32,
+                    y: (index / width.max(1)) as i32,
+                    vertical: vertical,
+                    kind: kind,
+                    open: 0.0,
+                }),
+                _ => None,
+            })
+            .collect::<Vec<Door>>();
+        let door_index = doors
+            .iter()
+            .enumerate()
+            .map(|(index, door)| ((door.x, door.y), index))
+            .collect();
+        Level {
+            name: String::from_utf8_lossy(&header.name[..name_length]).into_owned(),
+            width: width,
+            height: header.height.max(0) as usize,
+            objects: parse_objects(&objects, width, variant),
+            walls: walls,
+            extra: extra,
+            doors: doors,
+            door_index: door_index,
+            pushwall: None,
+            ceiling: WL6_CEILING[0],
+            fog: None,
+            variant: variant,
+        }
+    }
+
+    pub fn door(&self, x: i32, y: i32) -> Option<&Door> {
+        self.door_index.get(&(x, y)).map(|&index| &self.doors[index])
+    }
+
+    pub fn door_mut(&mut self, x: i32, y: i32) -> Option<&mut Door> {
+        let index = *self.door_index.get(&(x, y))?;
+        Some(&mut self.doors[index])
+    }
+
+    /// Raw wall plane value, None outside the map
+    pub fn wall(&self, x: i32, y: i32) -> Option<u16> {
+        if x < 0 || y < 0 || x as usize >= self.width || y as usize >= self.height {
+            return None;
+        }
+        self.walls.get(y as usize * self.width + x as usize).copied()
+    }
+
+    /// Tile at (x, y), the outside of the map is taken as solid wall
+    pub fn tile(&self, x: i32, y: i32) -> Tile {
+        self.wall(x, y)
+            .map_or(Tile::Wall { light: 0, dark: 1 }, Tile::from_tile)
+    }
+
+    /// A wall marked as secret in the object plane that has not been pushed yet
+    pub fn is_pushable(&self, x: i32, y: i32) -> bool {
+        self.tile(x, y).is_solid()
+            && self.objects.iter().any(|object| {
+                object.kind == ObjectKind::Pushwall && (object.x as i32, object.y as i32) == (x, y)
+            })
+    }
+
+    /// Starts sliding the secret wall at (x, y) towards `dir`. Fails when it is not
+    /// pushable, another one is moving or the tile behind it is not free
+    pub fn push_wall(&mut self, x: i32, y: i32, dir: Direction) -> bool {
+        let (dx, dy) = match dir {
+            Direction::East => (1, 0),
+            Direction::North => (0, -1),
+            Direction::West => (-1, 0),
+            Direction::South => (0, 1),
+            _ => return false,
+        };
+        if self.pushwall.is_some() || !self.is_pushable(x, y) || !self.is_free(x + dx, y + dy) {
+            return false;
+        }
+        let tile = match self.wall(x, y) {
+            Some(tile) => tile,
+            None => return false,
+        };
+        self.objects.retain(|object| {
+            object.kind != ObjectKind::Pushwall || (object.x as i32, object.y as i32) != (x, y)
+        });
+        // the vacated tile takes the floor it slides onto
+        let floor = self.wall(x + dx, y + dy).unwrap_or(AREA_TILE);
+        self.set_wall(x, y, floor);
+        self.pushwall = Some(Pushwall {
+            x: x,
+            y: y,
+            dx: dx,
+            dy: dy,
+            tile: tile,
+            offset: 0,
+        });
+        true
+    }
+
+    /// Moves the sliding wall on by `units`. It stops after two tiles or in front of
+    /// anything blocking its way and becomes a normal wall again
+    pub fn move_pushwall(&mut self, units: u32) {
+        let mut pushwall = match self.pushwall {
+            Some(pushwall) => pushwall,
+            None => return,
+        };
+        let limit = PUSHWALL_TILES * PUSHWALL_UNITS;
+        let before = pushwall.offset / PUSHWALL_UNITS;
+        pushwall.offset = (pushwall.offset + units).min(limit);
+        let mut tiles = pushwall.offset / PUSHWALL_UNITS;
+        // check the way ahead each time a tile boundary is reached
+        for tile in (before + 1)..=tiles.min(PUSHWALL_TILES - 1) {
+            let ahead = tile as i32 + 1;
+            if !self.is_free(pushwall.x + pushwall.dx * ahead, pushwall.y + pushwall.dy * ahead) {
+                tiles = tile;
+                pushwall.offset = limit;
+                break;
+            }
+        }
+        if pushwall.offset >= limit {
+            let tiles = tiles as i32;
+            self.set_wall(
+                pushwall.x + pushwall.dx * tiles,
+                pushwall.y + pushwall.dy * tiles,
+                pushwall.tile,
+            );
+            self.pushwall = None;
+        } else {
+            self.pushwall = Some(pushwall);
+        }
+    }
+
+    fn is_free(&self, x: i32, y: i32) -> bool {
+        matches!(self.tile(x, y), Tile::Floor { .. } | Tile::Ambush)
+    }
+
+    fn set_wall(&mut self, x: i32, y: i32, tile: u16) {
+        if self.wall(x, y).is_some() {
+            self.walls[y as usize * self.width + x as usize] = tile;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ceiling_color, DoorKind, Level, Tile, PUSHWALL_UNITS};
+    use crate::gamedata::GameVariant;
+    use crate::mapobject::{Direction, Key, ObjectKind};
+    use crate::wolf_asset::WolfLevel;
+
+    #[test]
+    fn ceiling_color_test() {
+        assert_eq!(ceiling_color(GameVariant::Registered, 1, 1), 0x1d);
+        assert_eq!(ceiling_color(GameVariant::Registered, 1, 10), 0xbf);
+        assert_eq!(ceiling_color(GameVariant::Registered, 6, 10), 0xdd);
+        assert_eq!(ceiling_color(GameVariant::SpearOfDestiny, 1, 21), 0xdc);
+        // out of range levels fall back to the first
+        assert_eq!(ceiling_color(GameVariant::SpearDemo, 1, 30), 0x6f);
+    }
+
+    #[test]
+    fn tile_test() {
+        assert_eq!(Tile::from_tile(1), Tile::Wall { light: 0, dark: 1 });
+        assert_eq!(
+            Tile::from_tile(21),
+            Tile::ElevatorSwitch {
+                light: 40,
+                dark: 41
+            }
+        );
+        assert_eq!(
+            Tile::from_tile(90),
+            Tile::Door {
+                vertical: true,
+                kind: DoorKind::Normal
+            }
+        );
+        assert_eq!(
+            Tile::from_tile(95),
+            Tile::Door {
+                vertical: false,
+                kind: DoorKind::Locked(Some(Key::Silver))
+            }
+        );
+        assert_eq!(
+            Tile::from_tile(100),
+            Tile::Door {
+                vertical: true,
+                kind: DoorKind::Elevator
+            }
+        );
+        assert_eq!(Tile::from_tile(106), Tile::Ambush);
+        assert_eq!(Tile::from_tile(110), Tile::Floor { area: 3 });
+        assert_eq!(Tile::from_tile(0), Tile::Unknown(0));
+    }
+
+    #[test]
+    fn level_test() {
+        let mut name = [0u8; 16];
+        name[..7].copy_from_slice(b"Wolf1 1");
+        let header = WolfLevel {
+            width: 3,
+            height: 2,
+            name: name,
+            ..Default::default()
+        };
+        let walls = vec![1, 91, 1, 1, 107, 1];
+        let mut objects = vec![0; 6];
+        objects[4] = 19;
+        let level = Level::new(&header, [walls, objects, vec![0; 6]], GameVariant::Registered);
+
+        assert_eq!(level.name, "Wolf1 1");
+        assert_eq!(level.objects.len(), 1);
+        assert_eq!((level.objects[0].x, level.objects[0].y), (1, 1));
+        assert_eq!(
+            level.objects[0].kind,
+            ObjectKind::PlayerStart {
+                facing: Direction::North
+            }
+        );
+        assert_eq!(level.tile(1, 1), Tile::Floor { area: 0 });
+        assert!(matches!(
+            level.tile(1, 0),
+            Tile::Door {
+                vertical: false,
+                ..
+            }
+        ));
+        assert_eq!(level.doors.len(), 1);
+        assert_eq!(level.door(1, 0).map(|door| door.open), Some(0.0));
+        assert!(level.door(1, 1).is_none());
+        assert_eq!(level.wall(3, 0), None);
+        assert!(level.tile(-1, 0).is_solid());
+    }
+
+    #[test]
+    fn pushwall_test() {
+        let header = WolfLevel {
+            width: 5,
+            height: 1,
+            ..Default::default()
+        };
+        let objects = vec![0, 98, 0, 0, 0];
+        let planes = [vec![1, 5, 108, 108, 108], objects.clone(), vec![0; 5]];
+        let mut level = Level::new(&header, planes, GameVariant::Registered);
+        assert!(level.is_pushable(1, 0));
+        assert!(!level.push_wall(1, 0, Direction::West));
+        assert!(!level.push_wall(1, 0, Direction::North));
+        assert!(level.push_wall(1, 0, Direction::East));
+        assert!(!level.is_pushable(1, 0));
+        assert_eq!(level.tile(1, 0), Tile::Floor { area: 1 });
+
+        level.move_pushwall(PUSHWALL_UNITS + 16);
+        let pushwall = level.pushwall.unwrap();
+        assert_eq!(pushwall.corner(), (2.25, 0.0));
+
+        // two tiles on it settles as a plain wall
+        level.move_pushwall(PUSHWALL_UNITS);
+        assert!(level.pushwall.is_none());
+        assert_eq!(level.wall(3, 0), Some(5));
+        assert_eq!(level.tile(2, 0), Tile::Floor { area: 1 });
+
+        // a wall in the way stops it after one tile
+        let planes = [vec![1, 5, 108, 2, 108], objects, vec![0; 5]];
+        let mut level = Level::new(&header, planes, GameVariant::Registered);
+        assert!(level.push_wall(1, 0, Direction::East));
+        level.move_pushwall(PUSHWALL_UNITS);
+        assert!(level.pushwall.is_none());
+        assert_eq!(level.wall(2, 0), Some(5));
+    }
+}
@@ -2,19 +2,111 @@
 #![allow(dead_code)]
 #![allow(unreachable_code)]
 #![allow(unused)]
+mod audiot;
 mod camera;
+mod colormap;
+mod gamedata;
+mod hud;
+mod imf;
 mod io;
+mod level;
+mod mapobject;
 mod math;
+mod opl;
 mod palette;
+mod palshift;
+mod pcspeaker;
+mod player;
+mod png;
+mod render;
 mod resource;
 mod texture;
 mod trait_def;
+mod vgagraph;
+mod wav;
+mod weapon;
 mod wolf_asset;
 mod statemachine;
 
 mod canvas;
+#[cfg(test)]
+mod golden;
+
+use camera::WolfCamera;
+use gamedata::{GameData, GameVariant};
+use hud::{status_rect, Hud, WL6_HUD_PICS};
+use mapobject::ObjectKind;
+use math::Vec2;
+use player::PlayerStatus;
+use render::Renderer;
+use resource::{MapCache, TexturePool};
+use vgagraph::VgaGraph;
+use weapon::{WeaponView, WL6_WEAPON_SPRITE};
+
+/// `wolf render <data dir> <episode> <level> <out.png> [width height]` renders the
+/// view from the player start into a PNG without opening a window, with the pistol
+/// and, for the registered version, the status bar
+fn render_png(args: &[String]) -> Result<(), String> {
+    if args.len() != 4 && args.len() != 6 {
+        return Err("usage: wolf render <data dir> <episode> <level> <out.png> [width height]".to_string());
+    }
+    let number = |arg: &String| arg.parse::<u32>().map_err(|e| format!("{}: {}", arg, e));
+    let (episode, level) = (number(&args[1])?, number(&args[2])?);
+    let resolution = if args.len() == 6 {
+        (number(&args[4])? as usize, number(&args[5])? as usize)
+    } else {
+        (320, 200)
+    };
+
+    let data = GameData::discover(&args[0]).map_err(|e| e.to_string())?;
+    let level = MapCache::load(&data)
+        .and_then(|mut maps| maps.read_level(episode, level))
+        .map_err(|e| e.to_string())?;
+    let textures = TexturePool::open(&data).map_err(|e| e.to_string())?;
+    let (pos, facing) = level
+        .objects
+        .iter()
+        .find_map(|object| match object.kind {
+            ObjectKind::PlayerStart { facing } => Some((
+                Vec2::new(object.x as f32 + 0.5, object.y as f32 + 0.5),
+                facing,
+            )),
+            _ => None,
+        })
+        .ok_or_else(|| format!("level {} has no player start", level.name))?;
+    let (x, y) = facing.vector();
+    let camera = WolfCamera::new(pos, Vec2::new(x, y), 60f32);
+
+    let mut renderer = Renderer::new(textures);
+    let mut canvas = renderer
+        .render_frame(&level, &camera, resolution)
+        .map_err(|e| e.to_string())?;
+    let status = PlayerStatus {
+        floor: number(&args[2])?,
+        ..Default::default()
+    };
+    // only the picture and sprite numbers of the registered version are known
+    if data.variant == GameVariant::Registered {
+        WeaponView::new(WL6_WEAPON_SPRITE)
+            .draw(status.weapon, renderer.textures(), &mut canvas, status_rect(resolution).1)
+            .map_err(|e| e.to_string())?;
+        let files = &data.files;
+        let hud = VgaGraph::open(&files.vga_head, &files.vga_dict, &files.vga_graph, data.vga_layout)
+            .and_then(|vga| Hud::load(&vga, WL6_HUD_PICS))
+            .map_err(|e| e.to_string())?;
+        hud.draw(&status, &mut canvas);
+    }
+    canvas.save_png(&args[3]).map_err(|e| e.to_string())
+}
 
 fn main() -> Result<(), i32> {
+    let args = std::env::args().skip(1).collect::<Vec<String>>();
+    if args.first().map(|arg| arg.as_str()) == Some("render") {
+        return render_png(&args[1..]).map_err(|e| {
+            eprintln!("{}", e);
+            1
+        });
+    }
     let a = 5;
     for b in 0..10{
         println!("{}", b);
@@ -34,10 +126,12 @@ mod dda_test {
     use std::collections::HashMap;
     use std::ops::Range;
 
-    use crate::{camera::WolfCamera, math::Grid2, resource::MapCache, resource::TexturePool};
+    use crate::{camera::WolfCamera, resource::MapCache, resource::TexturePool};
+    use crate::render::Renderer;
+    use crate::{gamedata::GameData, io::app_root_dir};
 
     use super::canvas;
-    use super::math::{Bound2, Vec2};
+    use super::math::Vec2;
     use canvas::{Canvas, Color};
 
 
@@ -65,84 +159,16 @@ mod dda_test {
         let default_format = canvas.default_pixel_format();
 
 
-        let mut rs = MapCache::load();
-        let map = rs.read_map(1, 1, 0);
-        let min = Vec2::<i32>::new(0, 0);
-        let max = Vec2::<i32>::new(map.width, map.height);
-        let b = Bound2::<i32>::new(min, max);
-        let g = Grid2::new(b);
+        let data = GameData::discover(app_root_dir().unwrap().join("resources/original")).unwrap();
+        let mut rs = MapCache::load(&data).unwrap();
+        let level = rs.read_level(1, 1).unwrap();
         let mut cam = WolfCamera::new(Vec2::new(32_f32, 32_f32), Vec2::new(1.0, 1.0), 45f32);
-        let mut wall_color_index = vec![0u8; canvas_res.1];
 
-        let mut tp = TexturePool::open();
+        let mut renderer = Renderer::new(TexturePool::open(&data).unwrap());
+        renderer.minimap = true;
 
         'running: loop {
-            //screen.clear();
-            screen.draw_ceil_and_floor();
-
-            let half_width = canvas_res.0 as f32 / 2_f32;
-            for col in 0..canvas_res.0 {
-                let angle = cam.get_view_angle()
-                    + ((col as f32 - half_width) / canvas_res.0 as f32) * cam.fov.to_radians();
-
-                for v in g.iter(cam.pos, angle) {
-                    let cell_index = v.0;
-                    let isect_pos = cam.dir * v.1 + cam.pos;
-
-
-                    let dxdy = isect_pos - cam.pos;
-                    let mut corrected = (dxdy.x * angle.cos() - dxdy.y * angle.sin());
-                    let mut wall_height = (1000.0f32 / corrected) as usize;
-                    if wall_height > canvas_res.1 {
-                        wall_height = canvas_res.1
-                    }
-
-                    let isect_index = (cell_index.y * 64 + cell_index.x) as usize;
-                    let a = map.data[isect_index];
-                    if a <= 107 {
-                        //draw column
-                        let tex = tp.get_texture(a as usize);
-                        //wall_color_index.fill(a as u8);
-                        let u = v.2;
-                        wall_color_index = (0..wall_height).map(|h|{
-                            tex.sample_nearest(u,1f32/wall_height as f32 * h as f32)
-                        }).collect();
-
-                        screen.set_wall(col, &wall_color_index[0..wall_height]);
-                        break;
-                    }
-                }
-            }
-
-
-            // minimap
-            {
-                (0..64).for_each(|x| {
-                    (0..64).for_each(|y| {
-                        let isect_index = (y * 64 + x) as usize;
-                        let color = map.data[isect_index];
-                        screen.set_pixel_by_color_index(x, y, color as u8);
-                    })
-                });
-                // player
-                let p = cam.pos;
-                screen.set_pixel_by_color_index(p.x as u32, p.y as u32, 50);
-
-                // view range
-                for col in 0..canvas_res.0 {
-                    let angle = cam.get_view_angle()
-                        + ((col as f32 - half_width) / canvas_res.0 as f32) * cam.fov.to_radians();
-                    for v in g.iter(cam.pos, angle) {
-                        let cell_index = v.0;
-                        let isect_index = (cell_index.y * 64 + cell_index.x) as usize;
-                        let a = map.data[isect_index];
-                        screen.set_pixel_by_color_index(v.0.x as u32, v.0.y as u32, 50);
-                        if a <= 107 {
-                            break;
-                        }
-                    }
-                }
-            }
+            renderer.render(&level, &cam, &mut screen).unwrap();
 
             let surface = Surface::from_data(
                 screen.buffer_as_mut(),
@@ -0,0 +1,466 @@
+use crate::gamedata::GameVariant;
+
+/// First static object tile in plane 1, statics are numbered from here
+const STATIC_TILE: u16 = 23;
+const LAST_STATIC_TILE: u16 = 74;
+/// Patrol turn points, one tile per direction starting at east
+const TURN_TILE: u16 = 90;
+const PUSHABLE_TILE: u16 = 98;
+const EXIT_TILE: u16 = 99;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Direction {
+    East,
+    NorthEast,
+    North,
+    NorthWest,
+    West,
+    SouthWest,
+    South,
+    SouthEast,
+}
+
+impl Direction {
+    /// Facing of a standing or patrolling actor, tiles come in groups of east, north, west, south
+    fn from_actor(offset: u16) -> Direction {
+        match offset % 4 {
+            0 => Direction::East,
+            1 => Direction::North,
+            2 => Direction::West,
+            _ => Direction::South,
+        }
+    }
+
+    /// Unit vector, y grows to the south as in the map
+    pub fn vector(&self) -> (f32, f32) {
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        match self {
+            Direction::East => (1.0, 0.0),
+            Direction::NorthEast => (diagonal, -diagonal),
+            Direction::North => (0.0, -1.0),
+            Direction::NorthWest => (-diagonal, -diagonal),
+            Direction::West => (-1.0, 0.0),
+            Direction::SouthWest => (-diagonal, diagonal),
+            Direction::South => (0.0, 1.0),
+            Direction::SouthEast => (diagonal, diagonal),
+        }
+    }
+}
+
+/// Lowest skill level an actor is spawned at
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Difficulty {
+    Easy,
+    Medium,
+    Hard,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum EnemyKind {
+    Guard,
+    Officer,
+    SS,
+    Dog,
+    Mutant,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BossKind {
+    Hans,
+    Schabbs,
+    FakeHitler,
+    Hitler,
+    Gretel,
+    Giftmacher,
+    FatFace,
+    Blinky,
+    Clyde,
+    Pinky,
+    Inky,
+    // Spear of Destiny
+    TransGrosse,
+    UberMutant,
+    Wilhelm,
+    DeathKnight,
+    Spectre,
+    Angel,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Key {
+    Gold,
+    Silver,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Pickup {
+    DogFood,
+    Food,
+    FirstAid,
+    Clip,
+    MachineGun,
+    ChainGun,
+    OneUp,
+    Gibs,
+    // Spear of Destiny
+    Clip25,
+    Spear,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Treasure {
+    Cross,
+    Chalice,
+    Chest,
+    Crown,
+}
+
+/// What a plane 1 tile spawns. `stat` is the static object number, sprite and
+/// behaviour of statics are looked up by it
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum ObjectKind {
+    PlayerStart {
+        facing: Direction,
+    },
+    Enemy {
+        kind: EnemyKind,
+        difficulty: Difficulty,
+        patrolling: bool,
+        facing: Direction,
+    },
+    Boss {
+        kind: BossKind,
+    },
+    DeadGuard,
+    Decoration {
+        stat: u8,
+        blocking: bool,
+    },
+    Pickup {
+        stat: u8,
+        pickup: Pickup,
+    },
+    Key {
+        stat: u8,
+        key: Key,
+    },
+    Treasure {
+        stat: u8,
+        treasure: Treasure,
+    },
+    TurnPoint {
+        direction: Direction,
+    },
+    Pushwall,
+    EndGame,
+}
+
+impl ObjectKind {
+    /// Decodes a plane 1 tile of `variant`, None for empty and unknown tiles
+    pub fn from_tile(tile: u16, variant: GameVariant) -> Option<ObjectKind> {
+        let kind = match tile {
+            19..=22 => ObjectKind::PlayerStart {
+                facing: match tile {
+                    19 => Direction::North,
+                    20 => Direction::East,
+                    21 => Direction::South,
+                    _ => Direction::West,
+                },
+            },
+            STATIC_TILE..=LAST_STATIC_TILE => static_object((tile - STATIC_TILE) as u8, variant),
+            TURN_TILE..=97 => ObjectKind::TurnPoint {
+                direction: [
+                    Direction::East,
+                    Direction::NorthEast,
+                    Direction::North,
+                    Direction::NorthWest,
+                    Direction::West,
+                    Direction::SouthWest,
+                    Direction::South,
+                    Direction::SouthEast,
+                ][(tile - TURN_TILE) as usize],
+            },
+            PUSHABLE_TILE => ObjectKind::Pushwall,
+            EXIT_TILE => ObjectKind::EndGame,
+            106 => boss(BossKind::Spectre),
+            107 => boss(BossKind::Angel),
+            124 => ObjectKind::DeadGuard,
+            125 => boss(BossKind::TransGrosse),
+            142 => boss(BossKind::UberMutant),
+            143 => boss(BossKind::Wilhelm),
+            160 => boss(BossKind::FakeHitler),
+            161 => boss(BossKind::DeathKnight),
+            178 => boss(BossKind::Hitler),
+            179 => boss(BossKind::FatFace),
+            196 => boss(BossKind::Schabbs),
+            197 => boss(BossKind::Gretel),
+            214 => boss(BossKind::Hans),
+            215 => boss(BossKind::Giftmacher),
+            224 => boss(BossKind::Blinky),
+            225 => boss(BossKind::Clyde),
+            226 => boss(BossKind::Pinky),
+            227 => boss(BossKind::Inky),
+            _ => return enemy(tile),
+        };
+        Some(kind)
+    }
+
+    /// Static object number of decorations and items
+    pub fn static_index(&self) -> Option<u8> {
+        match *self {
+            ObjectKind::Decoration { stat, .. }
+            | ObjectKind::Pickup { stat, .. }
+            | ObjectKind::Key { stat, .. }
+            | ObjectKind::Treasure { stat, .. } => Some(stat),
+            _ => None,
+        }
+    }
+}
+
+fn boss(kind: BossKind) -> ObjectKind {
+    ObjectKind::Boss { kind: kind }
+}
+
+/// Standing and patrolling actors come in groups of 8 tiles per kind and difficulty,
+/// 4 standing then 4 patrolling
+fn enemy(tile: u16) -> Option<ObjectKind> {
+    use Difficulty::*;
+    use EnemyKind::*;
+    let (kind, difficulty, base) = match tile {
+        108..=115 => (Guard, Easy, 108),
+        116..=123 => (Officer, Easy, 116),
+        126..=133 => (SS, Easy, 126),
+        134..=141 => (Dog, Easy, 134),
+        144..=151 => (Guard, Medium, 144),
+        152..=159 => (Officer, Medium, 152),
+        162..=169 => (SS, Medium, 162),
+        170..=177 => (Dog, Medium, 170),
+        180..=187 => (Guard, Hard, 180),
+        188..=195 => (Officer, Hard, 188),
+        198..=205 => (SS, Hard, 198),
+        206..=213 => (Dog, Hard, 206),
+        216..=223 => (Mutant, Easy, 216),
+        234..=241 => (Mutant, Medium, 234),
+        252..=259 => (Mutant, Hard, 252),
+        _ => return None,
+    };
+    Some(ObjectKind::Enemy {
+        kind: kind,
+        difficulty: difficulty,
+        patrolling: tile - base >= 4,
+        facing: Direction::from_actor(tile - base),
+    })
+}
+
+/// Static objects in the order of statinfo in WL_ACT1.C. Spear of Destiny blocks
+/// with its gibs instead of the kitchen stuff and pots, its red light no longer
+/// blocks like the Apogee sign, and its own statics replace the clip at the end
+fn static_object(stat: u8, variant: GameVariant) -> ObjectKind {
+    let spear = match variant {
+        GameVariant::Shareware | GameVariant::Registered => false,
+        GameVariant::SpearOfDestiny | GameVariant::SpearDemo => true,
+    };
+    let pickup = |pickup| ObjectKind::Pickup {
+        stat: stat,
+        pickup: pickup,
+    };
+    let treasure = |treasure| ObjectKind::Treasure {
+        stat: stat,
+        treasure: treasure,
+    };
+    let decoration = |blocking| ObjectKind::Decoration {
+        stat: stat,
+        blocking: blocking,
+    };
+    match (stat, spear) {
+        (6, _) => pickup(Pickup::DogFood),
+        (20, _) => ObjectKind::Key {
+            stat: stat,
+            key: Key::Gold,
+        },
+        (21, _) => ObjectKind::Key {
+            stat: stat,
+            key: Key::Silver,
+        },
+        (24, _) => pickup(Pickup::Food),
+        (25, _) => pickup(Pickup::FirstAid),
+        (26, _) | (48, false) => pickup(Pickup::Clip),
+        (27, _) => pickup(Pickup::MachineGun),
+        (28, _) => pickup(Pickup::ChainGun),
+        (29, _) => treasure(Treasure::Cross),
+        (30, _) => treasure(Treasure::Chalice),
+        (31, _) => treasure(Treasure::Chest),
+        (32, _) => treasure(Treasure::Crown),
+        (33, _) => pickup(Pickup::OneUp),
+        (34, _) | (38, _) => pickup(Pickup::Gibs),
+        (49, true) => pickup(Pickup::Clip25),
+        (51, true) => pickup(Pickup::Spear),
+        (1..=3, _) | (5, _) | (7, _) | (8, _) | (10..=13, _) | (16..=18, _) | (22, _)
+        | (35..=37, _) | (39, _) | (45, _) | (46, _) => decoration(true),
+        (40, false) => decoration(true),
+        (15, true) | (44, true) | (48, true) | (50, true) => decoration(true),
+        _ => decoration(false),
+    }
+}
+
+/// An object placed on a tile of the map
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MapObject {
+    pub x: u32,
+    pub y: u32,
+    pub kind: ObjectKind,
+}
+
+/// Decodes every known tile of an object plane of `variant` that is `width` tiles wide
+pub fn parse_objects(plane: &[u16], width: usize, variant: GameVariant) -> Vec<MapObject> {
+    plane
+        .iter()
+        .enumerate()
+        .filter_map(|(index, &tile)| {
+            ObjectKind::from_tile(tile, variant).map(|kind| MapObject {
+                x: (index % width.max(1)) as u32,
+                y: (index / width.max(1)) as u32,
+                kind: kind,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_objects, BossKind, Difficulty, Direction, EnemyKind, Key, ObjectKind, Pickup,
+        Treasure,
+    };
+    use crate::gamedata::GameVariant;
+
+    #[test]
+    fn object_tile_test() {
+        assert_eq!(
+            ObjectKind::from_tile(20, GameVariant::Registered),
+            Some(ObjectKind::PlayerStart {
+                facing: Direction::East
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(108, GameVariant::Registered),
+            Some(ObjectKind::Enemy {
+                kind: EnemyKind::Guard,
+                difficulty: Difficulty::Easy,
+                patrolling: false,
+                facing: Direction::East
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(205, GameVariant::Registered),
+            Some(ObjectKind::Enemy {
+                kind: EnemyKind::SS,
+                difficulty: Difficulty::Hard,
+                patrolling: true,
+                facing: Direction::South
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(239, GameVariant::Registered),
+            Some(ObjectKind::Enemy {
+                kind: EnemyKind::Mutant,
+                difficulty: Difficulty::Medium,
+                patrolling: true,
+                facing: Direction::North
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(214, GameVariant::Registered),
+            Some(ObjectKind::Boss {
+                kind: BossKind::Hans
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(43, GameVariant::Registered),
+            Some(ObjectKind::Key {
+                stat: 20,
+                key: Key::Gold
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(55, GameVariant::Registered),
+            Some(ObjectKind::Treasure {
+                stat: 32,
+                treasure: Treasure::Crown
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(48, GameVariant::Registered),
+            Some(ObjectKind::Pickup {
+                stat: 25,
+                pickup: Pickup::FirstAid
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(24, GameVariant::Registered),
+            Some(ObjectKind::Decoration {
+                stat: 1,
+                blocking: true
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(27, GameVariant::Registered),
+            Some(ObjectKind::Decoration {
+                stat: 4,
+                blocking: false
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(92, GameVariant::Registered),
+            Some(ObjectKind::TurnPoint {
+                direction: Direction::North
+            })
+        );
+        assert_eq!(ObjectKind::from_tile(98, GameVariant::Registered), Some(ObjectKind::Pushwall));
+        assert_eq!(ObjectKind::from_tile(99, GameVariant::Registered), Some(ObjectKind::EndGame));
+        assert_eq!(ObjectKind::from_tile(0, GameVariant::Registered), None);
+        assert_eq!(ObjectKind::from_tile(228, GameVariant::Registered), None);
+    }
+
+    #[test]
+    fn static_variant_test() {
+        let blocking = |tile, variant| match ObjectKind::from_tile(tile, variant) {
+            Some(ObjectKind::Decoration { blocking, .. }) => Some(blocking),
+            _ => None,
+        };
+        // kitchen stuff, Apogee sign and pots against gibs, red light and gibs
+        let wl6 = GameVariant::Registered;
+        let sod = GameVariant::SpearOfDestiny;
+        assert_eq!((blocking(38, wl6), blocking(38, sod)), (Some(false), Some(true)));
+        assert_eq!((blocking(63, wl6), blocking(63, sod)), (Some(true), Some(false)));
+        assert_eq!((blocking(67, wl6), blocking(67, sod)), (Some(false), Some(true)));
+        assert_eq!(blocking(71, sod), Some(true));
+        assert_eq!(
+            ObjectKind::from_tile(71, wl6),
+            Some(ObjectKind::Pickup {
+                stat: 48,
+                pickup: Pickup::Clip
+            })
+        );
+        assert_eq!(
+            ObjectKind::from_tile(74, sod),
+            Some(ObjectKind::Pickup {
+                stat: 51,
+                pickup: Pickup::Spear
+            })
+        );
+        assert_eq!(blocking(74, wl6), Some(false));
+    }
+
+    #[test]
+    fn parse_objects_test() {
+        let mut plane = vec![0u16; 4 * 3];
+        plane[1] = 19;
+        plane[2 * 4 + 3] = 98;
+        let objects = parse_objects(&plane, 4, GameVariant::Registered);
+        assert_eq!(objects.len(), 2);
+        assert_eq!((objects[0].x, objects[0].y), (1, 0));
+        assert_eq!((objects[1].x, objects[1].y), (3, 2));
+        assert_eq!(objects[1].kind, ObjectKind::Pushwall);
+    }
+}
@@ -26,7 +26,7 @@ where
     }
 }
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Vec2<T>
 where
     T: Primitive,
@@ -103,7 +103,7 @@ pub fn dot<T:Primitive>(v1:Vec2<T>,v2:Vec2<T>)->T{
 
 #[inline(always)]
 pub fn normalize<T:Primitive>(v:Vec2<T>)->Vec2<T>{
-    v / (dot(v,v).invsqrt())
+    v * dot(v,v).invsqrt()
 }
 
 
@@ -133,8 +133,22 @@ pub struct Grid2 {
     pub bound: Bound2<i32>,
 }
 
+/// A grid line crossed by a ray
+#[derive(Copy, Clone, Debug)]
+pub struct DDAHit {
+    /// the cell entered
+    pub cell: Vec2<i32>,
+    /// distance along the ray
+    pub t: f32,
+    /// position of the crossing along the face, 0..1
+    pub u: f32,
+    /// true when a face perpendicular to the x axis was crossed
+    pub vertical: bool,
+}
+
 // Bresenham
 pub struct DDAIterator<'a> {
+    origin:Vec2<f32>,
     txty:Vec2<f32>,
     dxdy:Vec2<f32>,
     rxry:Vec2<f32>,
@@ -165,6 +179,7 @@ impl<'a> DDAIterator<'a> {
         }
         let cell_index = Vec2::<i32> { x: pos.x as i32, y: pos.y as i32 };
         DDAIterator {
+            origin:pos,
             txty:txty,
             dxdy:dxdy,
             rxry:rxry,
@@ -174,53 +189,105 @@ impl<'a> DDAIterator<'a> {
     }
 }
 
+impl<'a> DDAIterator<'a> {
+    /// Where the ray crosses the plane through the middle of `cell`, parallel to the y
+    /// axis when `vertical`. Returns the distance and the position along the plane,
+    /// None when the ray leaves the cell first
+    pub fn midplane(&self, cell: Vec2<i32>, vertical: bool) -> Option<(f32, f32)> {
+        let (plane, origin, dir, across, across_dir, across_cell) = if vertical {
+            (cell.x, self.origin.x, self.rxry.x, self.origin.y, self.rxry.y, cell.y)
+        } else {
+            (cell.y, self.origin.y, self.rxry.y, self.origin.x, self.rxry.x, cell.x)
+        };
+        if dir == 0f32 {
+            return None;
+        }
+        let t = (plane as f32 + 0.5 - origin) / dir;
+        let hit = across + t * across_dir;
+        if t < 0f32 || hit.floor() as i32 != across_cell {
+            return None;
+        }
+        Some((t, hit - hit.floor()))
+    }
+
+    /// Where the ray runs into a one cell block whose top left corner is `corner`,
+    /// the block does not need to be aligned to the grid. `cell` of the hit is the
+    /// cell the block started from, `u` is measured from the block's corner
+    pub fn unit_block(&self, corner: Vec2<f32>) -> Option<DDAHit> {
+        let face = |origin: f32, dir: f32, min: f32| {
+            if dir > 0f32 {
+                Some((min - origin) / dir)
+            } else if dir < 0f32 {
+                Some((min + 1f32 - origin) / dir)
+            } else {
+                None
+            }
+        };
+        let inside = |v: f32, min: f32| v >= min && v <= min + 1f32;
+        let cell = Vec2::new(corner.x.floor() as i32, corner.y.floor() as i32);
+        let vertical = face(self.origin.x, self.rxry.x, corner.x)
+            .filter(|&t| t >= 0f32 && inside(self.origin.y + t * self.rxry.y, corner.y))
+            .map(|t| DDAHit {
+                cell: cell,
+                t: t,
+                u: (self.origin.y + t * self.rxry.y - corner.y).min(0.9999),
+                vertical: true,
+            });
+        let horizontal = face(self.origin.y, self.rxry.y, corner.y)
+            .filter(|&t| t >= 0f32 && inside(self.origin.x + t * self.rxry.x, corner.x))
+            .map(|t| DDAHit {
+                cell: cell,
+                t: t,
+                u: (self.origin.x + t * self.rxry.x - corner.x).min(0.9999),
+                vertical: false,
+            });
+        match (vertical, horizontal) {
+            (Some(v), Some(h)) => Some(if v.t <= h.t { v } else { h }),
+            (v, h) => v.or(h),
+        }
+    }
+}
+
 impl<'a> Iterator for DDAIterator<'a> {
-    type Item = (Vec2<i32>, f32, f32);
+    type Item = DDAHit;
     fn next(&mut self) -> Option<Self::Item> {
         let t;
-        let mut u=0f32;
-        if self.txty.x < self.txty.y{
-            // hit vertical wall
+        let vertical;
+        if self.txty.x <= self.txty.y{
+            // hit vertical wall, a corner counts as one
             t = self.txty.x;
+            vertical = true;
             self.txty.x += self.dxdy.x;
             if self.rxry.x < 0f32{
                 self.cell_index.x -= 1;
             }else{
                 self.cell_index.x +=1
             }
-            u = self.txty.y.fract();
-        }else if self.txty.y < self.txty.x{
+        }else{
             // hit horizontal wall
             t = self.txty.y;
+            vertical = false;
             self.txty.y += self.dxdy.y;
             if self.rxry.y < 0_f32{
                 self.cell_index.y -= 1;
             }else{
                 self.cell_index.y += 1;
             }
-            u = self.txty.x.fract();
-        }else{
-            t = self.txty.x;
-            self.txty.y += self.dxdy.y;
-            self.txty.x += self.dxdy.x;
-            if self.rxry.y < 0_f32{
-                self.cell_index.y -= 1;
-            }else{
-                self.cell_index.y += 1;
-            }
-            if self.rxry.x < 0f32{
-                self.cell_index.x -= 1;
-            }else{
-                self.cell_index.x +=1
-            }
         }
+        let along = if vertical {
+            self.origin.y + t * self.rxry.y
+        } else {
+            self.origin.x + t * self.rxry.x
+        };
         if (self.cell_index.x >= self.bound.min.x && self.cell_index.x < self.bound.max.x)
             && (self.cell_index.y >= self.bound.min.y && self.cell_index.y < self.bound.max.y)
         {
-            Some((
-                self.cell_index,
-                t,u
-            ))
+            Some(DDAHit {
+                cell: self.cell_index,
+                t: t,
+                u: along - along.floor(),
+                vertical: vertical,
+            })
         } else {
             None
         }
@@ -250,4 +317,37 @@ mod tests {
         g.iter(pos, std::f32::consts::FRAC_PI_4 * 3f32 )
             .for_each(|v| println!("{:?}", v));
     }
+
+    #[test]
+    fn dda_crossing_test() {
+        let g = Grid2::new(Bound2::new(Vec2::new(0, 0), Vec2::new(8, 8)));
+        // slightly south of east from (1.5, 1.25)
+        let angle = 0.25f32.atan2(2.0);
+        let mut iter = g.iter(Vec2::new(1.5, 1.25), angle);
+        let hit = iter.next().unwrap();
+        assert_eq!((hit.cell.x, hit.cell.y), (2, 1));
+        assert!(hit.vertical);
+        assert!((hit.u - 0.3125).abs() < 1e-4);
+
+        // the middle of the next cell is crossed half a cell further
+        let hit = iter.next().unwrap();
+        assert_eq!((hit.cell.x, hit.cell.y), (3, 1));
+        let (t, u) = iter.midplane(hit.cell, true).unwrap();
+        assert!((t - 2.0 * (1.0 + 0.25f32 * 0.25 / 4.0).sqrt()).abs() < 1e-4);
+        assert!((u - 0.5).abs() < 1e-4);
+        assert!(iter.midplane(Vec2::new(2, 1), false).is_none());
+    }
+
+    #[test]
+    fn dda_unit_block_test() {
+        let g = Grid2::new(Bound2::new(Vec2::new(0, 0), Vec2::new(8, 8)));
+        let iter = g.iter(Vec2::new(1.5, 1.5), 0f32);
+        // a block half way between two cells
+        let hit = iter.unit_block(Vec2::new(3.5, 1.25)).unwrap();
+        assert!(hit.vertical);
+        assert!((hit.t - 2.0).abs() < 1e-4);
+        assert!((hit.u - 0.25).abs() < 1e-4);
+        assert!(iter.unit_block(Vec2::new(3.5, 2.0)).is_none());
+        assert!(iter.unit_block(Vec2::new(-2.0, 1.0)).is_none());
+    }
 }
@@ -0,0 +1,421 @@
+use std::f32::consts::{LOG2_10, PI};
+
+/// Native sample rate of the YM3812, the frequency numbers are relative to it
+const OPL_RATE: f32 = 49716.0;
+const CHANNELS: usize = 9;
+const OPERATORS: usize = 18;
+/// Attenuation in dB at which an operator is silent
+const MAX_ATTENUATION: f32 = 96.0;
+/// Output of a single operator at full volume
+const OPERATOR_AMPLITUDE: f32 = 4096.0;
+
+const MULTIPLIERS: [f32; 16] = [
+    0.5, 1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 10.0, 12.0, 12.0, 15.0, 15.0,
+];
+
+/// Key scale attenuation in dB at block 7 by the upper 4 bits of the frequency number,
+/// 6 dB less per block below
+const KSL_DB: [f32; 16] = [
+    0.0, 9.0, 12.0, 13.875, 15.0, 16.125, 16.875, 17.625, 18.0, 18.75, 19.125, 19.5, 19.875, 20.25,
+    20.625, 21.0,
+];
+/// Fraction of the key scale attenuation applied for KSL 0..3 (0, 3, 1.5 and 6 dB/octave)
+const KSL_SCALE: [f32; 4] = [0.0, 0.5, 0.25, 1.0];
+
+/// Seconds to go through the whole attenuation range at rate 4, halved every 4 rates
+const ATTACK_TIME: f32 = 2.826;
+const DECAY_TIME: f32 = 39.28;
+
+/// Modulator slot of every channel, the carrier is 3 slots later
+const MODULATOR_SLOT: [usize; CHANNELS] = [0, 1, 2, 6, 7, 8, 12, 13, 14];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Stage {
+    Attack,
+    Decay,
+    Sustain,
+    Release,
+    Off,
+}
+
+#[derive(Copy, Clone)]
+struct Operator {
+    tremolo: bool,
+    vibrato: bool,
+    sustaining: bool, // EGT, hold the sustain level until key off
+    ksr: bool,
+    mult: u8,
+    ksl: u8,
+    total_level: u8,
+    attack: u8,
+    decay: u8,
+    sustain: u8,
+    release: u8,
+    waveform: u8,
+
+    phase: f32,    // in cycles
+    envelope: f32, // attenuation in dB
+    stage: Stage,
+    feedback: [f32; 2], // last two outputs, used by the modulator
+}
+
+impl Default for Operator {
+    fn default() -> Self {
+        Operator {
+            tremolo: false,
+            vibrato: false,
+            sustaining: false,
+            ksr: false,
+            mult: 0,
+            ksl: 0,
+            total_level: 0,
+            attack: 0,
+            decay: 0,
+            sustain: 0,
+            release: 0,
+            waveform: 0,
+            phase: 0.0,
+            envelope: MAX_ATTENUATION,
+            stage: Stage::Off,
+            feedback: [0.0; 2],
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default)]
+struct Channel {
+    fnum: u16,
+    block: u8,
+    key_on: bool,
+    feedback: u8,
+    additive: bool,
+}
+
+/// Software YM3812 (OPL2) in melodic mode.
+///
+/// Register writes follow the chip, output is signed 16-bit mono at `sample_rate`.
+/// Envelopes are computed in dB and move linearly, which is close to but not bit exact
+/// with the hardware
+pub struct Opl2 {
+    sample_rate: u32,
+    operators: [Operator; OPERATORS],
+    channels: [Channel; CHANNELS],
+    waveform_select: bool,
+    deep_tremolo: bool,
+    deep_vibrato: bool,
+    lfo_time: f32,
+}
+
+impl Opl2 {
+    pub fn new(sample_rate: u32) -> Self {
+        Opl2 {
+            sample_rate: sample_rate.max(1),
+            operators: [Operator::default(); OPERATORS],
+            channels: [Channel::default(); CHANNELS],
+            waveform_select: false,
+            deep_tremolo: false,
+            deep_vibrato: false,
+            lfo_time: 0.0,
+        }
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        let slot = |base: u8| operator_slot((register - base) as usize);
+        match register {
+            0x01 => self.waveform_select = value & 0x20 != 0,
+            0x20..=0x35 => {
+                if let Some(slot) = slot(0x20) {
+                    let op = &mut self.operators[slot];
+                    op.tremolo = value & 0x80 != 0;
+                    op.vibrato = value & 0x40 != 0;
+                    op.sustaining = value & 0x20 != 0;
+                    op.ksr = value & 0x10 != 0;
+                    op.mult = value & 0x0F;
+                }
+            }
+            0x40..=0x55 => {
+                if let Some(slot) = slot(0x40) {
+                    let op = &mut self.operators[slot];
+                    op.ksl = value >> 6;
+                    op.total_level = value & 0x3F;
+                }
+            }
+            0x60..=0x75 => {
+                if let Some(slot) = slot(0x60) {
+                    let op = &mut self.operators[slot];
+                    op.attack = value >> 4;
+                    op.decay = value & 0x0F;
+                }
+            }
+            0x80..=0x95 => {
+                if let Some(slot) = slot(0x80) {
+                    let op = &mut self.operators[slot];
+                    op.sustain = value >> 4;
+                    op.release = value & 0x0F;
+                }
+            }
+            0xA0..=0xA8 => {
+                let channel = &mut self.channels[(register - 0xA0) as usize];
+                channel.fnum = (channel.fnum & 0x300) | value as u16;
+            }
+            0xB0..=0xB8 => {
+                let index = (register - 0xB0) as usize;
+                let key_on = value & 0x20 != 0;
+                let channel = &mut self.channels[index];
+                channel.fnum = (channel.fnum & 0xFF) | ((value as u16 & 0x03) << 8);
+                channel.block = (value >> 2) & 0x07;
+                let was_on = channel.key_on;
+                channel.key_on = key_on;
+                let modulator = MODULATOR_SLOT[index];
+                for &slot in &[modulator, modulator + 3] {
+                    let op = &mut self.operators[slot];
+                    if key_on && !was_on {
+                        op.stage = Stage::Attack;
+                        op.phase = 0.0;
+                    } else if !key_on && was_on && op.stage != Stage::Off {
+                        op.stage = Stage::Release;
+                    }
+                }
+            }
+            0xBD => {
+                self.deep_tremolo = value & 0x80 != 0;
+                self.deep_vibrato = value & 0x40 != 0;
+            }
+            0xC0..=0xC8 => {
+                let channel = &mut self.channels[(register - 0xC0) as usize];
+                channel.feedback = (value >> 1) & 0x07;
+                channel.additive = value & 0x01 != 0;
+            }
+            0xE0..=0xF5 => {
+                if let Some(slot) = slot(0xE0) {
+                    self.operators[slot].waveform = value & 0x03;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Fills `out` with the next samples
+    pub fn generate(&mut self, out: &mut [i16]) {
+        out.iter_mut().for_each(|sample| *sample = self.sample());
+    }
+
+    pub fn sample(&mut self) -> i16 {
+        let dt = 1.0 / self.sample_rate as f32;
+        self.lfo_time = (self.lfo_time + dt) % 100.0;
+        // tremolo at 3.7 Hz, vibrato at 6.1 Hz
+        let tremolo_depth = if self.deep_tremolo { 4.8 } else { 1.0 };
+        let tremolo = tremolo_depth * 0.5 * (1.0 + (2.0 * PI * 3.7 * self.lfo_time).sin());
+        let vibrato_cents = if self.deep_vibrato { 14.0 } else { 7.0 };
+        let vibrato = (vibrato_cents / 1200.0 * (2.0 * PI * 6.1 * self.lfo_time).sin()).exp2();
+
+        let mut mix = 0f32;
+        for (index, &modulator) in MODULATOR_SLOT.iter().enumerate() {
+            let channel = self.channels[index];
+            let carrier = modulator + 3;
+            if self.operators[modulator].stage == Stage::Off
+                && self.operators[carrier].stage == Stage::Off
+            {
+                continue;
+            }
+            let frequency =
+                channel.fnum as f32 * OPL_RATE / (1u32 << (20 - channel.block as u32)) as f32;
+
+            let feedback = if channel.feedback > 0 {
+                let history = self.operators[modulator].feedback;
+                (history[0] + history[1]) * 0.5 * (channel.feedback as f32 - 6.0).exp2()
+            } else {
+                0.0
+            };
+            let modulation =
+                self.operator_output(modulator, &channel, feedback, tremolo, vibrato, frequency);
+            {
+                let op = &mut self.operators[modulator];
+                op.feedback = [op.feedback[1], modulation];
+            }
+            // a full scale modulator shifts the carrier by two cycles
+            let phase_shift = if channel.additive {
+                0.0
+            } else {
+                modulation * 2.0
+            };
+            let output =
+                self.operator_output(carrier, &channel, phase_shift, tremolo, vibrato, frequency);
+            mix += if channel.additive {
+                modulation + output
+            } else {
+                output
+            };
+        }
+        (mix * OPERATOR_AMPLITUDE)
+            .round()
+            .max(i16::MIN as f32)
+            .min(i16::MAX as f32) as i16
+    }
+
+    /// Output of operator `slot` in -1..1, then advances its phase and envelope
+    fn operator_output(
+        &mut self,
+        slot: usize,
+        channel: &Channel,
+        phase_shift: f32,
+        tremolo: f32,
+        vibrato: f32,
+        frequency: f32,
+    ) -> f32 {
+        let waveform_select = self.waveform_select;
+        let sample_rate = self.sample_rate as f32;
+        let op = &mut self.operators[slot];
+
+        let mut attenuation = op.envelope + op.total_level as f32 * 0.75;
+        let ksl_octave = (channel.fnum >> 6) as usize & 0x0F;
+        let ksl = (KSL_DB[ksl_octave] - 6.0 * (7 - channel.block) as f32).max(0.0);
+        attenuation += ksl * KSL_SCALE[op.ksl as usize];
+        if op.tremolo {
+            attenuation += tremolo;
+        }
+        let output = if op.stage == Stage::Off || attenuation >= MAX_ATTENUATION {
+            0.0
+        } else {
+            let waveform = if waveform_select { op.waveform } else { 0 };
+            wave(waveform, op.phase + phase_shift) * (-attenuation / 20.0 * LOG2_10).exp2()
+        };
+
+        let mut increment = frequency * MULTIPLIERS[op.mult as usize] / sample_rate;
+        if op.vibrato {
+            increment *= vibrato;
+        }
+        op.phase = (op.phase + increment).fract();
+
+        // rate offset from the block and the top bit of the frequency number
+        let offset = (channel.block << 1) | (channel.fnum >> 9) as u8 & 1;
+        let offset = if op.ksr { offset } else { offset >> 2 };
+        let step = |rate: u8, time: f32| -> f32 {
+            if rate == 0 {
+                return 0.0;
+            }
+            let rate = (rate * 4 + offset).min(63);
+            let seconds =
+                time / (rate as f32 / 4.0 - 1.0).floor().exp2() / (1.0 + 0.25 * (rate % 4) as f32);
+            MAX_ATTENUATION / (seconds * sample_rate)
+        };
+        let sustain_level = if op.sustain == 15 {
+            93.0
+        } else {
+            op.sustain as f32 * 3.0
+        };
+        match op.stage {
+            Stage::Attack => {
+                if op.attack == 15 {
+                    op.envelope = 0.0;
+                } else {
+                    op.envelope -= step(op.attack, ATTACK_TIME);
+                }
+                if op.envelope <= 0.0 {
+                    op.envelope = 0.0;
+                    op.stage = Stage::Decay;
+                }
+            }
+            Stage::Decay => {
+                op.envelope += step(op.decay, DECAY_TIME);
+                if op.envelope >= sustain_level {
+                    op.envelope = sustain_level;
+                    op.stage = Stage::Sustain;
+                }
+            }
+            Stage::Sustain => {
+                // percussive sounds keep fading while the key is held
+                if !op.sustaining {
+                    op.envelope += step(op.release, DECAY_TIME);
+                }
+            }
+            Stage::Release => op.envelope += step(op.release, DECAY_TIME),
+            Stage::Off => {}
+        }
+        if op.envelope >= MAX_ATTENUATION {
+            op.envelope = MAX_ATTENUATION;
+            if op.stage != Stage::Attack {
+                op.stage = Stage::Off;
+            }
+        }
+        output
+    }
+}
+
+/// Operator slot of a register offset 0x00..0x15, the gaps are not connected
+fn operator_slot(offset: usize) -> Option<usize> {
+    match offset {
+        0x00..=0x05 => Some(offset),
+        0x08..=0x0D => Some(offset - 2),
+        0x10..=0x15 => Some(offset - 4),
+        _ => None,
+    }
+}
+
+/// One of the four OPL2 waveforms at `phase` cycles
+fn wave(waveform: u8, phase: f32) -> f32 {
+    let phase = phase.rem_euclid(1.0);
+    let sine = (2.0 * PI * phase).sin();
+    match waveform {
+        0 => sine,
+        1 => sine.max(0.0),
+        2 => sine.abs(),
+        _ => {
+            if phase % 0.5 < 0.25 {
+                sine.abs()
+            } else {
+                0.0
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Opl2;
+
+    /// channel 0 as a plain sine on the carrier, modulator muted
+    fn sine_voice(opl: &mut Opl2) {
+        opl.write(0x20, 0x21); // modulator: sustaining, mult 1
+        opl.write(0x40, 0x3F); // modulator silent
+        opl.write(0x23, 0x21); // carrier: sustaining, mult 1
+        opl.write(0x43, 0x00);
+        opl.write(0x63, 0xF0); // instant attack
+        opl.write(0x83, 0x0F); // sustain at full level, fast release
+        opl.write(0xC0, 0x00);
+    }
+
+    #[test]
+    fn opl_sine_test() {
+        let rate = 44100;
+        let mut opl = Opl2::new(rate);
+        sine_voice(&mut opl);
+        // 440 Hz: fnum = 440 * 2^(20 - 4) / 49716 = 580
+        opl.write(0xA0, (580 & 0xFF) as u8);
+        opl.write(0xB0, 0x20 | (4 << 2) | (580 >> 8) as u8);
+
+        let mut out = vec![0i16; rate as usize];
+        opl.generate(&mut out);
+        let crossings = out.windows(2).filter(|w| w[0] < 0 && w[1] >= 0).count();
+        assert!((437..=443).contains(&crossings), "{} cycles", crossings);
+        let peak = out.iter().map(|s| (*s as i32).abs()).max().unwrap();
+        assert!(peak > 3000, "peak {}", peak);
+
+        // key off fades to silence
+        opl.write(0xB0, (4 << 2) | (580 >> 8) as u8);
+        opl.generate(&mut out);
+        assert!(out[out.len() - 1000..].iter().all(|&s| s == 0));
+    }
+
+    #[test]
+    fn opl_silent_test() {
+        let mut opl = Opl2::new(22050);
+        let mut out = vec![1i16; 1000];
+        opl.generate(&mut out);
+        assert!(out.iter().all(|&s| s == 0));
+    }
+}
@@ -1,12 +1,22 @@
 #![allow(dead_code)]
-pub fn rgb(x: u32, y: u32, z: u32) -> (u8, u8, u8,u8) {
+use crate::wolf_asset::{Result, WolfAssetError};
+
+use std::path::Path;
+
+/// RGBA colour of every palette index
+pub type Palette = [(u8, u8, u8, u8); 256];
+
+/// Bytes of a VGA palette: an RGB triple per index
+pub const PALETTE_BYTES: usize = 256 * 3;
+
+/// Scales a 6-bit VGA DAC value triple to 8 bits per channel
+pub const fn rgb(x: u32, y: u32, z: u32) -> (u8, u8, u8,u8) {
     ((x * 255 / 63) as u8, (y * 255 / 63) as u8, (z * 255 / 63) as u8, 255)
 }
 
-///
-/// This need to be compile-time constants
-pub fn wolf_palette() -> Vec<(u8, u8, u8, u8)> {
-    vec![
+/// The game palette of Wolfenstein 3D (GAMEPAL.OBJ). Spear of Destiny palettes are
+/// not shipped, they are loaded with `read_palette`
+pub const WOLF_PALETTE: Palette = [
         rgb(0, 0, 0),
         rgb(0, 0, 42),
         rgb(0, 42, 0),
@@ -263,5 +273,92 @@ pub fn wolf_palette() -> Vec<(u8, u8, u8, u8)> {
         rgb(0, 28, 28),
         rgb(0, 27, 27),
         rgb(38, 0, 34),
-    ]
+];
+
+/// A palette blended towards `color`, 0 keeps it, 1 replaces every entry
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Shift {
+    pub color: (u8, u8, u8),
+    pub amount: f32,
+}
+
+impl Shift {
+    pub fn apply(&self, palette: &Palette) -> Palette {
+        let amount = self.amount.clamp(0.0, 1.0);
+        let blend = |c: u8, to: u8| (c as f32 + (to as f32 - c as f32) * amount).round() as u8;
+        let mut shifted = *palette;
+        for (r, g, b, _) in shifted.iter_mut() {
+            *r = blend(*r, self.color.0);
+            *g = blend(*g, self.color.1);
+            *b = blend(*b, self.color.2);
+        }
+        shifted
+    }
+}
+
+/// Reads a VGA palette from `data`, as found in .pal files and the palette chunks
+/// of VGAGRAPH. Values are 6-bit DAC values, unless one of them is above 63 in
+/// which case the palette is taken to have 8 bits per channel already
+pub fn parse_palette(data: &[u8]) -> Result<Palette> {
+    if data.len() < PALETTE_BYTES {
+        return Err(WolfAssetError::TruncatedHeader {
+            offset: 0,
+            expected: PALETTE_BYTES,
+        });
+    }
+    let data = &data[..PALETTE_BYTES];
+    let wide = data.iter().any(|&value| value > 63);
+    let mut palette = [(0, 0, 0, 255); 256];
+    for (color, triple) in palette.iter_mut().zip(data.chunks_exact(3)) {
+        *color = if wide {
+            (triple[0], triple[1], triple[2], 255)
+        } else {
+            rgb(triple[0] as u32, triple[1] as u32, triple[2] as u32)
+        };
+    }
+    Ok(palette)
+}
+
+/// Reads a .pal file, see `parse_palette`
+pub fn read_palette<P: AsRef<Path>>(path: P) -> Result<Palette> {
+    let data = std::fs::read(path).map_err(|e| WolfAssetError::Io { offset: 0, source: e })?;
+    parse_palette(&data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_palette, Shift, WOLF_PALETTE};
+
+    #[test]
+    fn parse_palette_test() {
+        let vga = (0..256 * 3).map(|i| (i % 64) as u8).collect::<Vec<u8>>();
+        let palette = parse_palette(&vga).unwrap();
+        assert_eq!(palette[0], (0, 4, 8, 255));
+        assert_eq!(palette[21], (255, 0, 4, 255));
+
+        let mut wide = vga.clone();
+        wide[5] = 200;
+        assert_eq!(parse_palette(&wide).unwrap()[1], (3, 4, 200, 255));
+        assert!(parse_palette(&vga[..767]).is_err());
+
+        // the game palette survives a round trip through 6-bit values
+        let dac = WOLF_PALETTE
+            .iter()
+            .flat_map(|&(r, g, b, _)| [r, g, b].map(|c| ((c as u32 * 63 + 127) / 255) as u8))
+            .collect::<Vec<u8>>();
+        assert!(parse_palette(&dac).unwrap() == WOLF_PALETTE);
+    }
+
+    #[test]
+    fn shift_test() {
+        let half = Shift {
+            color: (255, 0, 0),
+            amount: 0.5,
+        };
+        let shifted = half.apply(&WOLF_PALETTE);
+        assert_eq!(shifted[0], (128, 0, 0, 255));
+        assert_eq!(shifted[15], (255, 128, 128, 255));
+        let none = Shift { amount: 0.0, ..half };
+        assert!(none.apply(&WOLF_PALETTE) == WOLF_PALETTE);
+    }
 }
@@ -0,0 +1,184 @@
+//! Screen flashes and fades done by shifting the palette, after WL_PLAY.C. The
+//! game loop reports damage, pickups and level changes, advances the shifts by
+//! the tics of every frame and hands `shift()` to `Canvas::set_shift`
+use crate::palette::Shift;
+
+/// The original runs its game loop at 70 tics per second
+pub const TICS_PER_SECOND: u32 = 70;
+
+/// Damage flashes go in eighths towards red, bonus flashes in twentieths
+/// towards yellowish white
+const NUM_RED_SHIFTS: i32 = 6;
+const RED_STEPS: f32 = 8.0;
+const NUM_WHITE_SHIFTS: i32 = 3;
+const WHITE_STEPS: f32 = 20.0;
+const WHITE_TICS: i32 = 6;
+const RED: (u8, u8, u8) = (255, 0, 0);
+const WHITE: (u8, u8, u8) = (255, 250, 0);
+/// Length of the fades between levels and screens, VL_FadeOut uses 30 steps
+pub const FADE_TICS: u32 = 30;
+
+/// A blend towards `color` from `from` to `to` over `tics`
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Fade {
+    color: (u8, u8, u8),
+    from: f32,
+    to: f32,
+    tics: u32,
+    elapsed: u32,
+}
+
+impl Fade {
+    fn amount(&self) -> f32 {
+        let progress = self.elapsed.min(self.tics) as f32 / self.tics.max(1) as f32;
+        self.from + (self.to - self.from) * progress
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct PaletteShifts {
+    damage: i32,
+    bonus: i32,
+    fade: Option<Fade>,
+}
+
+impl PaletteShifts {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// The flash grows with the damage taken and lasts a tic per point
+    pub fn damage_flash(&mut self, damage: i32) {
+        self.damage += damage.max(0);
+    }
+
+    pub fn bonus_flash(&mut self) {
+        self.bonus = NUM_WHITE_SHIFTS * WHITE_TICS;
+    }
+
+    /// Fades the screen out to `color` over `tics`, it stays that way until the
+    /// next fade in
+    pub fn fade_out(&mut self, color: (u8, u8, u8), tics: u32) {
+        let from = self.fade.map_or(0.0, |fade| fade.amount());
+        self.fade = Some(Fade {
+            color: color,
+            from: from,
+            to: 1.0,
+            tics: tics,
+            elapsed: 0,
+        });
+    }
+
+    /// Fades in from the colour of the last fade out, from black when there was none
+    pub fn fade_in(&mut self, tics: u32) {
+        let (color, from) = self.fade.map_or(((0, 0, 0), 1.0), |fade| (fade.color, fade.amount()));
+        self.fade = Some(Fade {
+            color: color,
+            from: from,
+            to: 0.0,
+            tics: tics,
+            elapsed: 0,
+        });
+    }
+
+    /// True while a fade is still running
+    pub fn is_fading(&self) -> bool {
+        self.fade.is_some_and(|fade| fade.elapsed < fade.tics)
+    }
+
+    /// Drops every flash and fade, as when a level starts
+    pub fn clear(&mut self) {
+        *self = Default::default();
+    }
+
+    pub fn update(&mut self, tics: u32) {
+        let tics = tics.min(i32::MAX as u32) as i32;
+        self.bonus = (self.bonus - tics).max(0);
+        self.damage = (self.damage - tics).max(0);
+        if let Some(fade) = self.fade.as_mut() {
+            fade.elapsed = fade.elapsed.saturating_add(tics as u32);
+            // a finished fade in leaves nothing behind
+            if fade.elapsed >= fade.tics && fade.to == 0.0 {
+                self.fade = None;
+            }
+        }
+    }
+
+    /// The palette shift for this frame. A fade wins over the flashes, red over
+    /// white, as in UpdatePaletteShifts
+    pub fn shift(&self) -> Option<Shift> {
+        if let Some(fade) = self.fade {
+            return Some(Shift {
+                color: fade.color,
+                amount: fade.amount(),
+            });
+        }
+        if self.damage > 0 {
+            let red = (self.damage / 10 + 1).min(NUM_RED_SHIFTS);
+            return Some(Shift {
+                color: RED,
+                amount: red as f32 / RED_STEPS,
+            });
+        }
+        if self.bonus > 0 {
+            let white = (self.bonus / WHITE_TICS + 1).min(NUM_WHITE_SHIFTS);
+            return Some(Shift {
+                color: WHITE,
+                amount: white as f32 / WHITE_STEPS,
+            });
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{PaletteShifts, FADE_TICS, RED, WHITE};
+
+    #[test]
+    fn flash_test() {
+        let mut shifts = PaletteShifts::new();
+        assert_eq!(shifts.shift(), None);
+
+        shifts.bonus_flash();
+        let bonus = shifts.shift().unwrap();
+        assert_eq!((bonus.color, bonus.amount), (WHITE, 3.0 / 20.0));
+        shifts.update(13);
+        assert_eq!(shifts.shift().unwrap().amount, 1.0 / 20.0);
+
+        // damage wins over the bonus, big hits are capped at the sixth shift
+        shifts.damage_flash(100);
+        let damage = shifts.shift().unwrap();
+        assert_eq!((damage.color, damage.amount), (RED, 6.0 / 8.0));
+        shifts.update(95);
+        assert_eq!(shifts.shift().unwrap().amount, 1.0 / 8.0);
+        shifts.update(5);
+        assert_eq!(shifts.shift(), None);
+    }
+
+    #[test]
+    fn fade_test() {
+        let mut shifts = PaletteShifts::new();
+        shifts.damage_flash(20);
+        shifts.fade_out((0, 0, 0), FADE_TICS);
+        assert_eq!(shifts.shift().unwrap().amount, 0.0);
+        shifts.update(FADE_TICS / 2);
+        assert!(shifts.is_fading());
+        assert_eq!(shifts.shift().unwrap().amount, 0.5);
+
+        // a fade out holds the colour until the fade in is done
+        shifts.update(FADE_TICS);
+        assert!(!shifts.is_fading());
+        assert_eq!(shifts.shift().unwrap().amount, 1.0);
+        shifts.fade_in(10);
+        shifts.update(5);
+        assert_eq!(shifts.shift().unwrap().amount, 0.5);
+        shifts.update(5);
+        assert!(!shifts.is_fading());
+        assert_eq!(shifts.shift(), None);
+
+        shifts.bonus_flash();
+        shifts.clear();
+        assert_eq!(shifts.shift(), None);
+    }
+}
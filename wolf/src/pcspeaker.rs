@@ -0,0 +1,119 @@
+use crate::audiot::PcSound;
+
+/// PC speaker sounds advance one byte per tick at 140 Hz
+pub const PC_SOUND_TICK_RATE: u32 = 140;
+/// Input clock of the PC timer
+const PIT_RATE: f32 = 1_193_181.0;
+/// Sound bytes are timer divisors divided by 60
+const DIVISOR_SCALE: f32 = 60.0;
+const AMPLITUDE: i16 = 8192;
+
+/// Turns the timer divisor bytes of a PC speaker sound into a square wave
+pub struct PcSpeakerPlayer<'a> {
+    sound: &'a PcSound,
+    sample_rate: u32,
+    position: usize, // next byte
+    phase: f32,      // in cycles
+    frequency: f32,  // 0 while silent
+    tick_samples_left: u32,
+}
+
+impl<'a> PcSpeakerPlayer<'a> {
+    pub fn new(sound: &'a PcSound, sample_rate: u32) -> Self {
+        PcSpeakerPlayer {
+            sound: sound,
+            sample_rate: sample_rate.max(1),
+            position: 0,
+            phase: 0.0,
+            frequency: 0.0,
+            tick_samples_left: 0,
+        }
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.position >= self.sound.data.len() && self.tick_samples_left == 0
+    }
+
+    /// Renders up to `out.len()` samples, returns how many were written.
+    /// Less than `out.len()` means the sound ended
+    pub fn render(&mut self, out: &mut [i16]) -> usize {
+        let mut written = 0;
+        while written < out.len() {
+            if self.tick_samples_left == 0 && !self.next_tick() {
+                break;
+            }
+            let step = self.frequency / self.sample_rate as f32;
+            while self.tick_samples_left > 0 && written < out.len() {
+                out[written] = if self.frequency == 0.0 {
+                    0
+                } else if self.phase < 0.5 {
+                    AMPLITUDE
+                } else {
+                    -AMPLITUDE
+                };
+                self.phase = (self.phase + step).fract();
+                self.tick_samples_left -= 1;
+                written += 1;
+            }
+        }
+        written
+    }
+
+    fn next_tick(&mut self) -> bool {
+        let value = match self.sound.data.get(self.position) {
+            Some(&value) => value,
+            None => return false,
+        };
+        let frequency = if value == 0 {
+            0.0
+        } else {
+            PIT_RATE / (value as f32 * DIVISOR_SCALE)
+        };
+        if frequency == 0.0 {
+            self.phase = 0.0;
+        }
+        self.frequency = frequency;
+        // spread the rounding so that n ticks always last n / 140 seconds
+        let rate = self.sample_rate as u64;
+        let start = self.position as u64 * rate / PC_SOUND_TICK_RATE as u64;
+        self.position += 1;
+        let end = self.position as u64 * rate / PC_SOUND_TICK_RATE as u64;
+        self.tick_samples_left = (end - start) as u32;
+        true
+    }
+}
+
+/// Renders a whole PC speaker sound as signed 16-bit mono samples
+pub fn render_pc_sound(sound: &PcSound, sample_rate: u32) -> Vec<i16> {
+    let rate = sample_rate.max(1) as usize;
+    let mut samples = vec![0i16; sound.data.len() * rate / PC_SOUND_TICK_RATE as usize];
+    let written = PcSpeakerPlayer::new(sound, sample_rate).render(&mut samples);
+    samples.truncate(written);
+    samples
+}
+
+#[cfg(test)]
+mod tests {
+    use super::render_pc_sound;
+    use crate::audiot::PcSound;
+
+    #[test]
+    fn pc_speaker_test() {
+        // one second of divisor 600 (about 1988.6 Hz), then a silent tick
+        let mut data = vec![10u8; 140];
+        data.push(0);
+        let sound = PcSound {
+            priority: 0,
+            data: data,
+        };
+        let samples = render_pc_sound(&sound, 44100);
+        assert_eq!(samples.len(), 141 * 44100 / 140);
+
+        let rising = samples[..44100]
+            .windows(2)
+            .filter(|w| w[0] < 0 && w[1] > 0)
+            .count();
+        assert!((1987..=1990).contains(&rising), "{} cycles", rising);
+        assert!(samples[44100..].iter().all(|&s| s == 0));
+    }
+}
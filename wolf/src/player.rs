@@ -0,0 +1,121 @@
+//! What the status bar and the weapon view show of the player
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Weapon {
+    Knife,
+    Pistol,
+    MachineGun,
+    ChainGun,
+}
+
+impl Weapon {
+    /// Position in the original's weapon order, as used by the pictures and sprites
+    pub fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+/// BJ looks around on the status bar: left, straight ahead or right
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Glance {
+    Left,
+    Ahead,
+    Right,
+}
+
+/// Face animation of UpdateFace in WL_AGENT.C: after a random number of tics BJ
+/// picks a new direction to look at
+#[derive(Clone, Debug)]
+pub struct Face {
+    pub glance: Glance,
+    count: u32,
+    seed: u32,
+}
+
+impl Face {
+    pub fn new(seed: u32) -> Self {
+        Face {
+            glance: Glance::Ahead,
+            count: 0,
+            seed: seed | 1,
+        }
+    }
+
+    /// 0..=255 like US_RndT, from a xorshift instead of the original table
+    fn random(&mut self) -> u32 {
+        self.seed ^= self.seed << 13;
+        self.seed ^= self.seed >> 17;
+        self.seed ^= self.seed << 5;
+        self.seed & 0xFF
+    }
+
+    pub fn update(&mut self, tics: u32) {
+        self.count += tics;
+        if self.count > self.random() {
+            // the original draws 0..=3 and looks straight ahead twice as often
+            self.glance = match self.random() >> 6 {
+                0 => Glance::Left,
+                2 => Glance::Right,
+                _ => Glance::Ahead,
+            };
+            self.count = 0;
+        }
+    }
+}
+
+impl Default for Face {
+    fn default() -> Self {
+        Face::new(1)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct PlayerStatus {
+    /// level number as shown, counting from 1
+    pub floor: u32,
+    pub score: u32,
+    pub lives: u32,
+    /// 0..=100, 0 is dead
+    pub health: u32,
+    pub ammo: u32,
+    pub gold_key: bool,
+    pub silver_key: bool,
+    pub weapon: Weapon,
+    pub face: Face,
+}
+
+impl Default for PlayerStatus {
+    /// The state of a new game in WL_MAIN.C
+    fn default() -> Self {
+        PlayerStatus {
+            floor: 1,
+            score: 0,
+            lives: 3,
+            health: 100,
+            ammo: 8,
+            gold_key: false,
+            silver_key: false,
+            weapon: Weapon::Pistol,
+            face: Face::default(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Face, Glance};
+
+    #[test]
+    fn face_test() {
+        let mut face = Face::new(7);
+        let mut seen = Vec::new();
+        for _ in 0..1000 {
+            face.update(4);
+            if !seen.contains(&face.glance) {
+                seen.push(face.glance);
+            }
+        }
+        assert_eq!(seen.len(), 3);
+        assert!(seen.contains(&Glance::Left) && seen.contains(&Glance::Right));
+    }
+}
@@ -0,0 +1,127 @@
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::Path;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A];
+/// Largest block deflate can store uncompressed
+const STORED_BLOCK: usize = 0xFFFF;
+
+fn crc32(chunks: &[&[u8]]) -> u32 {
+    let mut crc = 0xFFFF_FFFFu32;
+    for byte in chunks.iter().flat_map(|chunk| chunk.iter()) {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                0xEDB8_8320 ^ (crc >> 1)
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for &byte in data {
+        a = (a + byte as u32) % 65521;
+        b = (b + a) % 65521;
+    }
+    (b << 16) | a
+}
+
+fn write_chunk<W: Write>(writer: &mut W, kind: &[u8; 4], data: &[u8]) -> Result<()> {
+    writer.write_all(&(data.len() as u32).to_be_bytes())?;
+    writer.write_all(kind)?;
+    writer.write_all(data)?;
+    writer.write_all(&crc32(&[kind, data]).to_be_bytes())
+}
+
+/// Writes 8-bit RGBA pixels, row by row, as a PNG image. The image data is kept in
+/// stored deflate blocks, no compression is attempted
+pub fn write_png<W: Write>(writer: &mut W, width: u32, height: u32, rgba: &[u8]) -> Result<()> {
+    let stride = width as usize * 4;
+    if rgba.len() != stride * height as usize {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            "pixel data does not match the image size",
+        ));
+    }
+
+    writer.write_all(&SIGNATURE)?;
+    let mut header = Vec::with_capacity(13);
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bits per channel, RGBA, deflate, adaptive filtering, no interlace
+    header.extend_from_slice(&[8, 6, 0, 0, 0]);
+    write_chunk(writer, b"IHDR", &header)?;
+
+    // every scanline starts with its filter type, 0 is none
+    let mut scanlines = Vec::with_capacity((stride + 1) * height as usize);
+    for row in rgba.chunks_exact(stride.max(1)) {
+        scanlines.push(0);
+        scanlines.extend_from_slice(row);
+    }
+
+    let blocks = scanlines.len() / STORED_BLOCK + 1;
+    let mut zlib = Vec::with_capacity(scanlines.len() + blocks * 5 + 6);
+    zlib.extend_from_slice(&[0x78, 0x01]);
+    let mut remaining = scanlines.as_slice();
+    loop {
+        let length = remaining.len().min(STORED_BLOCK);
+        let last = length == remaining.len();
+        zlib.push(last as u8);
+        zlib.extend_from_slice(&(length as u16).to_le_bytes());
+        zlib.extend_from_slice(&(!(length as u16)).to_le_bytes());
+        zlib.extend_from_slice(&remaining[..length]);
+        remaining = &remaining[length..];
+        if last {
+            break;
+        }
+    }
+    zlib.extend_from_slice(&adler32(&scanlines).to_be_bytes());
+    write_chunk(writer, b"IDAT", &zlib)?;
+    write_chunk(writer, b"IEND", &[])
+}
+
+pub fn save_png<P: AsRef<Path>>(path: P, width: u32, height: u32, rgba: &[u8]) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_png(&mut writer, width, height, rgba)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{adler32, crc32, save_png, write_png};
+
+    #[test]
+    fn checksum_test() {
+        assert_eq!(crc32(&[b"IEND"]), 0xAE42_6082);
+        assert_eq!(adler32(b"Wikipedia"), 0x11E6_0398);
+    }
+
+    #[test]
+    fn png_round_trip_test() {
+        let (width, height) = (300u32, 70u32);
+        let rgba = (0..width * height * 4)
+            .map(|i| (i * 7 % 251) as u8)
+            .collect::<Vec<u8>>();
+
+        let mut bytes = Vec::new();
+        write_png(&mut bytes, width, height, &rgba).unwrap();
+        assert_eq!(&bytes[1..4], b"PNG");
+        assert!(write_png(&mut Vec::new(), width, height + 1, &rgba).is_err());
+
+        // more than one stored block, read back by stb_image
+        let path = std::env::temp_dir().join(format!("wolf_png_{}.png", std::process::id()));
+        save_png(&path, width, height, &rgba).unwrap();
+        match stb_image::image::load(&path) {
+            stb_image::image::LoadResult::ImageU8(image) => {
+                assert_eq!((image.width, image.height, image.depth), (300, 70, 4));
+                assert!(image.data == rgba);
+            }
+            _ => panic!("not decoded"),
+        }
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,883 @@
+use crate::camera::WolfCamera;
+use crate::canvas::Canvas;
+use crate::colormap::{ColorMap, Fog, FULL_BRIGHT};
+use crate::gamedata::GameVariant;
+use crate::level::{DoorKind, Level, Tile, FLOOR_COLOR};
+use crate::mapobject::{BossKind, Difficulty, EnemyKind, ObjectKind};
+use crate::math::{dot, normalize, Bound2, Grid2, Vec2};
+use crate::palette::Palette;
+use crate::resource::TexturePool;
+use crate::texture::Sprite;
+use crate::wolf_asset::Result;
+
+use rayon::prelude::*;
+use std::f32::consts::FRAC_PI_4;
+use std::ops::Range;
+
+/// Palette index of the player and the view rays on the minimap
+const MINIMAP_VIEW_COLOR: u8 = 50;
+/// Sprites closer than this are behind the near plane
+const MIN_DEPTH: f32 = 0.2;
+
+// WL6 sprite numbers (WL_DEF.H), counted from the first sprite page of VSWAP. The
+// shareware version shares them, Spear of Destiny has its own
+const SPR_STAT_0: usize = 2;
+const SPR_GRD_S_1: usize = 50;
+const SPR_GRD_DEAD: usize = 95;
+const SPR_DOG_W1_1: usize = 99;
+const SPR_SS_S_1: usize = 138;
+const SPR_MUT_S_1: usize = 187;
+const SPR_OFC_S_1: usize = 238;
+const SPR_BLINKY_W1: usize = 288;
+const SPR_PINKY_W1: usize = 290;
+const SPR_CLYDE_W1: usize = 292;
+const SPR_INKY_W1: usize = 294;
+const SPR_BOSS_W1: usize = 296;
+const SPR_SCHABB_W1: usize = 307;
+const SPR_FAKE_W1: usize = 321;
+const SPR_MECHA_W1: usize = 334;
+const SPR_GIFT_W1: usize = 360;
+const SPR_GRETEL_W1: usize = 385;
+const SPR_FAT_W1: usize = 396;
+
+/// VSWAP pages to texture the floor and ceiling with, not part of the original
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Flats {
+    pub floor: usize,
+    pub ceiling: usize,
+}
+
+/// Draws complete frames of a level: ceiling and floor, walls, sprites and
+/// optionally the minimap in the top left corner
+pub struct Renderer {
+    textures: TexturePool,
+    /// actors of higher difficulties are not shown
+    pub difficulty: Difficulty,
+    pub minimap: bool,
+    /// cast rays and fill rows on the rayon thread pool
+    pub parallel: bool,
+    /// textured floor and ceiling instead of the flat colours
+    pub flats: Option<Flats>,
+    /// remap tables for the fog of the last level drawn
+    colormap: Option<ColorMap>,
+    /// wall distance per column of the last frame
+    depth: Vec<f32>,
+}
+
+impl Renderer {
+    pub fn new(textures: TexturePool) -> Self {
+        Renderer {
+            textures: textures,
+            difficulty: Difficulty::Hard,
+            minimap: false,
+            parallel: true,
+            flats: None,
+            colormap: None,
+            depth: Vec::new(),
+        }
+    }
+
+    pub fn textures(&mut self) -> &mut TexturePool {
+        &mut self.textures
+    }
+
+    /// Renders one frame at `resolution` into a new canvas, no window is needed
+    pub fn render_frame(
+        &mut self,
+        level: &Level,
+        camera: &WolfCamera,
+        resolution: (usize, usize),
+    ) -> Result<Canvas> {
+        let mut canvas = Canvas::new(resolution);
+        self.render(level, camera, &mut canvas)?;
+        Ok(canvas)
+    }
+
+    /// Fails when a wall page or a sprite in view cannot be loaded
+    pub fn render(
+        &mut self,
+        level: &Level,
+        camera: &WolfCamera,
+        canvas: &mut Canvas,
+    ) -> Result<()> {
+        self.set_fog(level.fog, canvas.palette());
+        match (self.flats, &self.colormap) {
+            (Some(flats), _) => self.draw_flats(camera, flats, canvas)?,
+            (None, Some(colormap)) => {
+                let focal = camera.focal_length(canvas.size().0);
+                let height = canvas.size().1;
+                canvas.draw_rows(self.parallel, |row, pixels, palette| {
+                    let (color, distance) = flat_row(focal, height, row);
+                    let color = if color == FlatRow::Ceiling { level.ceiling } else { FLOOR_COLOR };
+                    let (r, g, b, a) = palette[colormap.at(distance)[color as usize] as usize];
+                    for pixel in pixels.chunks_exact_mut(4) {
+                        pixel.copy_from_slice(&[r, g, b, a]);
+                    }
+                });
+            }
+            (None, None) => canvas.draw_ceil_and_floor(level.ceiling, FLOOR_COLOR),
+        }
+        self.draw_walls(level, camera, canvas)?;
+        let sprites = object_sprites(level, camera.pos, self.difficulty);
+        draw_sprites(
+            canvas,
+            camera,
+            &sprites,
+            &self.depth,
+            &mut self.textures,
+            self.colormap.as_ref(),
+        )?;
+        if self.minimap {
+            draw_minimap(level, camera, canvas);
+        }
+        Ok(())
+    }
+
+    /// The remap tables take a moment to build, they are kept until the fog or the
+    /// palette changes
+    fn set_fog(&mut self, fog: Option<Fog>, palette: &Palette) {
+        let current = self.colormap.as_ref();
+        if current.map(|colormap| colormap.fog()) != fog
+            || current.is_some_and(|colormap| colormap.palette() != palette)
+        {
+            self.colormap = fog.map(|fog| ColorMap::new(palette, fog));
+        }
+    }
+
+    /// Floor casting: every screen row below the horizon sees the floor at one
+    /// distance, which is walked across the row from the leftmost to the rightmost
+    /// ray
+    fn draw_flats(&mut self, camera: &WolfCamera, flats: Flats, canvas: &mut Canvas) -> Result<()> {
+        let (width, height) = canvas.size();
+        let focal = camera.focal_length(width);
+        self.textures.get_texture(flats.floor)?;
+        self.textures.get_texture(flats.ceiling)?;
+        let (textures, colormap) = (&self.textures, self.colormap.as_ref());
+        let (left, step) = (column_ray(camera, width, 0), camera.plane() * (2.0 / width as f32));
+        canvas.draw_rows(self.parallel, |row, pixels, palette| {
+            let (page, distance) = match flat_row(focal, height, row) {
+                (FlatRow::Ceiling, distance) => (flats.ceiling, distance),
+                (FlatRow::Floor, distance) => (flats.floor, distance),
+            };
+            let texture = match textures.texture(page) {
+                Some(texture) => texture,
+                None => return,
+            };
+            let shade = colormap.map_or(&FULL_BRIGHT, |colormap| colormap.at(distance));
+            let mut spot = camera.pos + left * distance;
+            let step = step * distance;
+            for pixel in pixels.chunks_exact_mut(4) {
+                let (u, v) = (spot.x - spot.x.floor(), spot.y - spot.y.floor());
+                let (r, g, b, a) = palette[shade[texture.sample_nearest(u, v) as usize] as usize];
+                pixel.copy_from_slice(&[r, g, b, a]);
+                spot = spot + step;
+            }
+        });
+        Ok(())
+    }
+
+    /// Rays are cast per column, then the framebuffer is filled row by row so the
+    /// rows can be handed out to worker threads without sharing pixels
+    fn draw_walls(&mut self, level: &Level, camera: &WolfCamera, canvas: &mut Canvas) -> Result<()> {
+        let (width, height) = canvas.size();
+        let door_wall = self.textures.door_wall();
+        let focal = camera.focal_length(width);
+        let cast = |col| cast_column(level, camera, door_wall, width, col);
+        let casts: Vec<Option<(WallHit, f32)>> = if self.parallel {
+            (0..width).into_par_iter().map(cast).collect()
+        } else {
+            (0..width).map(cast).collect()
+        };
+
+        self.depth.clear();
+        self.depth
+            .extend(casts.iter().map(|cast| cast.map_or(f32::MAX, |(_, distance)| distance)));
+        // pages are loaded up front, the row workers only read them
+        for (hit, _) in casts.iter().flatten() {
+            self.textures.get_texture(hit.page)?;
+        }
+
+        let colormap = self.colormap.as_ref();
+        let spans = casts
+            .iter()
+            .map(|cast| {
+                cast.map(|(hit, distance)| WallSpan {
+                    shade: colormap.map_or(&FULL_BRIGHT, |colormap| colormap.at(distance)),
+                    ..WallSpan::new(hit, focal / distance, height)
+                })
+            })
+            .collect::<Vec<Option<WallSpan>>>();
+        let textures = &self.textures;
+        canvas.draw_rows(self.parallel, |row, pixels, palette| {
+            for (col, span) in spans.iter().enumerate() {
+                let span = match span {
+                    Some(span) if span.rows.contains(&row) => span,
+                    _ => continue,
+                };
+                let texture = match textures.texture(span.page) {
+                    Some(texture) => texture,
+                    None => continue,
+                };
+                let v = ((row as f32 + 0.5 - span.top) / span.height).clamp(0.0, 0.999);
+                let (r, g, b, a) = palette[span.shade[texture.sample_nearest(span.u, v) as usize] as usize];
+                pixels[col * 4..col * 4 + 4].copy_from_slice(&[r, g, b, a]);
+            }
+        });
+        Ok(())
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum FlatRow {
+    Ceiling,
+    Floor,
+}
+
+/// Whether screen `row` shows ceiling or floor and at which distance, the eye is
+/// half a wall above the floor. The ceiling mirrors the floor about the horizon
+fn flat_row(focal: f32, height: usize, row: usize) -> (FlatRow, f32) {
+    let (flat, offset) = if row < height / 2 {
+        (FlatRow::Ceiling, height / 2 - row)
+    } else {
+        (FlatRow::Floor, row + 1 - height / 2)
+    };
+    (flat, focal / 2.0 / (offset as f32 - 0.5).max(0.5))
+}
+
+/// Screen rows covered by the wall of one column
+struct WallSpan<'a> {
+    page: usize,
+    /// remap table for the light level at the wall's distance
+    shade: &'a [u8; 256],
+    u: f32,
+    /// the wall is centred on the horizon, rows beyond the screen are cut off
+    rows: Range<usize>,
+    top: f32,
+    height: f32,
+}
+
+impl WallSpan<'_> {
+    fn new(hit: WallHit, wall_height: f32, screen_height: usize) -> Self {
+        let rows = (wall_height as usize).min(screen_height);
+        let first = screen_height / 2 - rows / 2;
+        WallSpan {
+            page: hit.page,
+            shade: &FULL_BRIGHT,
+            u: hit.u,
+            rows: first..first + rows,
+            top: (screen_height as f32 - wall_height) / 2.0,
+            height: wall_height,
+        }
+    }
+}
+
+/// Direction of the ray through the centre of screen column `col`, through the
+/// camera plane
+fn column_ray(camera: &WolfCamera, width: usize, col: usize) -> Vec2<f32> {
+    camera.ray(2.0 * (col as f32 + 0.5) / width as f32 - 1.0)
+}
+
+/// The wall seen in screen column `col` and its perpendicular distance to the
+/// camera plane, which unlike the distance along the ray keeps flat walls flat
+pub fn cast_column(
+    level: &Level,
+    camera: &WolfCamera,
+    door_wall: usize,
+    width: usize,
+    col: usize,
+) -> Option<(WallHit, f32)> {
+    let ray = normalize(column_ray(camera, width, col));
+    let hit = trace(level, door_wall, camera.pos, ray.y.atan2(ray.x))?;
+    Some((hit, hit.t * dot(ray, camera.dir)))
+}
+
+/// One pixel per tile with the tile value as colour, the player and the cells the
+/// view rays pass before they reach a wall
+pub fn draw_minimap(level: &Level, camera: &WolfCamera, canvas: &mut Canvas) {
+    let (width, height) = canvas.size();
+    let visible = |x: i32, y: i32| {
+        (x as usize) < width.min(level.width) && (y as usize) < height.min(level.height)
+    };
+    for y in 0..level.height as i32 {
+        for x in 0..level.width as i32 {
+            if let (true, Some(tile)) = (visible(x, y), level.wall(x, y)) {
+                canvas.set_pixel_by_color_index(x as u32, y as u32, tile as u8);
+            }
+        }
+    }
+    if camera.pos.x >= 0.0
+        && camera.pos.y >= 0.0
+        && visible(camera.pos.x as i32, camera.pos.y as i32)
+    {
+        canvas.set_pixel_by_color_index(
+            camera.pos.x as u32,
+            camera.pos.y as u32,
+            MINIMAP_VIEW_COLOR,
+        );
+    }
+
+    let grid = Grid2::new(Bound2::new(
+        Vec2::new(0, 0),
+        Vec2::new(level.width as i32, level.height as i32),
+    ));
+    for col in 0..width {
+        let ray = column_ray(camera, width, col);
+        for hit in grid.iter(camera.pos, ray.y.atan2(ray.x)) {
+            if level.tile(hit.cell.x, hit.cell.y).is_solid() {
+                break;
+            }
+            if visible(hit.cell.x, hit.cell.y) {
+                canvas.set_pixel_by_color_index(
+                    hit.cell.x as u32,
+                    hit.cell.y as u32,
+                    MINIMAP_VIEW_COLOR,
+                );
+            }
+        }
+    }
+}
+
+/// Offsets from `TexturePool::door_wall` of the door pages
+const DOOR_PAGE: usize = 0;
+const DOOR_SIDE_PAGE: usize = 2;
+const ELEVATOR_DOOR_PAGE: usize = 4;
+const LOCKED_DOOR_PAGE: usize = 6;
+
+/// The first wall or closed part of a door a ray runs into
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WallHit {
+    /// distance along the ray
+    pub t: f32,
+    /// texture column, 0..1
+    pub u: f32,
+    /// VSWAP page of the texture
+    pub page: usize,
+}
+
+/// Walks the ray from `origin` through the level. Doors are thin walls through the
+/// middle of their tile, slid sideways by their open fraction, and the walls next
+/// to a door show the door side texture. A moving pushwall is hit wherever it is
+/// between two tiles
+pub fn trace(level: &Level, door_wall: usize, origin: Vec2<f32>, angle: f32) -> Option<WallHit> {
+    let bound = Bound2::new(
+        Vec2::new(0, 0),
+        Vec2::new(level.width as i32, level.height as i32),
+    );
+    let grid = Grid2::new(bound);
+    let mut iter = grid.iter(origin, angle);
+    let (rx, ry) = (angle.cos(), angle.sin());
+    // keep textures reading left to right on every face
+    let mirror = |vertical: bool, u: f32| {
+        let mirrored = if vertical { rx < 0.0 } else { ry > 0.0 };
+        if mirrored {
+            // a hit right on a grid line has u = 0, keep it inside the texture
+            (1.0 - u).min(0.9999)
+        } else {
+            u
+        }
+    };
+    let block = level.pushwall.and_then(|pushwall| {
+        let (x, y) = pushwall.corner();
+        let hit = iter.unit_block(Vec2::new(x, y))?;
+        let page = match Tile::from_tile(pushwall.tile) {
+            Tile::Wall { light, dark } | Tile::ElevatorSwitch { light, dark } => {
+                if hit.vertical {
+                    dark
+                } else {
+                    light
+                }
+            }
+            _ => return None,
+        };
+        Some(WallHit {
+            t: hit.t,
+            u: mirror(hit.vertical, hit.u),
+            page: page as usize,
+        })
+    });
+    let nearest = |hit: WallHit| match block {
+        Some(block) if block.t < hit.t => block,
+        _ => hit,
+    };
+    let mut previous = Vec2::new(origin.x.floor() as i32, origin.y.floor() as i32);
+    while let Some(hit) = iter.next() {
+        match level.tile(hit.cell.x, hit.cell.y) {
+            Tile::Wall { light, dark } | Tile::ElevatorSwitch { light, dark } => {
+                let page = match (level.door(previous.x, previous.y), hit.vertical) {
+                    (Some(_), vertical) => door_wall + DOOR_SIDE_PAGE + vertical as usize,
+                    (None, true) => dark as usize,
+                    (None, false) => light as usize,
+                };
+                return Some(nearest(WallHit {
+                    t: hit.t,
+                    u: mirror(hit.vertical, hit.u),
+                    page: page,
+                }));
+            }
+            Tile::Door { vertical, kind } => {
+                if let Some((t, u)) = iter.midplane(hit.cell, vertical) {
+                    let open = level
+                        .door(hit.cell.x, hit.cell.y)
+                        .map_or(0.0, |door| door.open);
+                    if u >= open {
+                        let first = match kind {
+                            DoorKind::Normal => DOOR_PAGE,
+                            DoorKind::Elevator => ELEVATOR_DOOR_PAGE,
+                            DoorKind::Locked(_) => LOCKED_DOOR_PAGE,
+                        };
+                        return Some(nearest(WallHit {
+                            t: t,
+                            u: u - open,
+                            page: door_wall + first + vertical as usize,
+                        }));
+                    }
+                }
+            }
+            _ => {}
+        }
+        previous = hit.cell;
+    }
+    block
+}
+
+/// A sprite standing on the floor, centred on `pos`
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WorldSprite {
+    pub pos: Vec2<f32>,
+    pub sprite: usize,
+}
+
+/// Sprites of the statics and of the actors spawned at `difficulty`. Actors with
+/// eight rotations show the frame matching the side `viewer` looks at them from.
+/// Only Wolfenstein 3D levels get sprites, there is no table for Spear of Destiny
+pub fn object_sprites(
+    level: &Level,
+    viewer: Vec2<f32>,
+    difficulty: Difficulty,
+) -> Vec<WorldSprite> {
+    match level.variant {
+        GameVariant::Shareware | GameVariant::Registered => {}
+        GameVariant::SpearOfDestiny | GameVariant::SpearDemo => return Vec::new(),
+    }
+    level
+        .objects
+        .iter()
+        .filter_map(|object| {
+            let pos = Vec2::new(object.x as f32 + 0.5, object.y as f32 + 0.5);
+            let sprite = match object.kind {
+                ObjectKind::Enemy {
+                    kind,
+                    difficulty: spawn,
+                    patrolling,
+                    facing,
+                } => {
+                    if spawn > difficulty {
+                        return None;
+                    }
+                    // standing dogs have no frames of their own and use the first walk frame
+                    let first = match (kind, patrolling) {
+                        (EnemyKind::Dog, _) => SPR_DOG_W1_1,
+                        (EnemyKind::Guard, false) => SPR_GRD_S_1,
+                        (EnemyKind::Officer, false) => SPR_OFC_S_1,
+                        (EnemyKind::SS, false) => SPR_SS_S_1,
+                        (EnemyKind::Mutant, false) => SPR_MUT_S_1,
+                        (EnemyKind::Guard, true) => SPR_GRD_S_1 + 8,
+                        (EnemyKind::Officer, true) => SPR_OFC_S_1 + 8,
+                        (EnemyKind::SS, true) => SPR_SS_S_1 + 8,
+                        (EnemyKind::Mutant, true) => SPR_MUT_S_1 + 8,
+                    };
+                    first + rotation(facing.vector(), viewer - pos)
+                }
+                ObjectKind::Boss { kind } => boss_sprite(kind)?,
+                ObjectKind::DeadGuard => SPR_GRD_DEAD,
+                _ => SPR_STAT_0 + object.kind.static_index()? as usize,
+            };
+            Some(WorldSprite {
+                pos: pos,
+                sprite: sprite,
+            })
+        })
+        .collect()
+}
+
+/// Spear of Destiny bosses are not part of the WL6 sprite set
+fn boss_sprite(kind: BossKind) -> Option<usize> {
+    Some(match kind {
+        BossKind::Hans => SPR_BOSS_W1,
+        BossKind::Schabbs => SPR_SCHABB_W1,
+        BossKind::FakeHitler => SPR_FAKE_W1,
+        BossKind::Hitler => SPR_MECHA_W1,
+        BossKind::Giftmacher => SPR_GIFT_W1,
+        BossKind::Gretel => SPR_GRETEL_W1,
+        BossKind::FatFace => SPR_FAT_W1,
+        BossKind::Blinky => SPR_BLINKY_W1,
+        BossKind::Pinky => SPR_PINKY_W1,
+        BossKind::Clyde => SPR_CLYDE_W1,
+        BossKind::Inky => SPR_INKY_W1,
+        _ => return None,
+    })
+}
+
+/// Which of the eight rotation frames to show, 0 when the actor faces the viewer.
+/// Angles grow counter-clockwise on the map as in CalcRotate
+fn rotation(facing: (f32, f32), to_viewer: Vec2<f32>) -> usize {
+    let angle = |x: f32, y: f32| (-y).atan2(x);
+    let relative = angle(to_viewer.x, to_viewer.y) - angle(facing.0, facing.1);
+    ((relative / FRAC_PI_4).round() as i32).rem_euclid(8) as usize
+}
+
+/// Screen column of the sprite centre and its distance to the camera plane, None
+/// behind the camera
+pub fn project(camera: &WolfCamera, width: usize, pos: Vec2<f32>) -> Option<(f32, f32)> {
+    let to_sprite = pos - camera.pos;
+    let right = Vec2::new(-camera.dir.y, camera.dir.x);
+    let depth = dot(to_sprite, camera.dir);
+    if depth < MIN_DEPTH {
+        return None;
+    }
+    let column =
+        width as f32 / 2.0 + dot(to_sprite, right) / depth * camera.focal_length(width);
+    Some((column, depth))
+}
+
+/// Draws `sprites` from back to front, `depth` holds the wall distance of every column
+pub fn draw_sprites(
+    canvas: &mut Canvas,
+    camera: &WolfCamera,
+    sprites: &[WorldSprite],
+    depth: &[f32],
+    textures: &mut TexturePool,
+    colormap: Option<&ColorMap>,
+) -> Result<()> {
+    let width = canvas.size().0;
+    let focal = camera.focal_length(width);
+    let mut visible = sprites
+        .iter()
+        .filter_map(|s| project(camera, width, s.pos).map(|(column, d)| (column, d, s.sprite)))
+        .collect::<Vec<(f32, f32, usize)>>();
+    visible.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    for (column, d, sprite) in visible {
+        let shade = colormap.map_or(&FULL_BRIGHT, |colormap| colormap.at(d));
+        draw_sprite(canvas, textures.get_sprite(sprite)?, column, d, focal, depth, shade);
+    }
+    Ok(())
+}
+
+/// Draws one sprite centred on screen column `column` at distance `distance`, one
+/// tile wide and high like the walls seen through `focal`. A column is skipped when
+/// the wall in it is closer. Texels are remapped through `shade`
+pub fn draw_sprite(
+    canvas: &mut Canvas,
+    sprite: &Sprite,
+    column: f32,
+    distance: f32,
+    focal: f32,
+    depth: &[f32],
+    shade: &[u8; 256],
+) {
+    let (width, height) = canvas.size();
+    let size = focal / distance;
+    let left = column - size / 2.0;
+    let top = height as f32 / 2.0 - size / 2.0;
+    let first_col = left.max(0.0) as usize;
+    let last_col = ((left + size).ceil().max(0.0) as usize).min(width);
+    let first_row = top.max(0.0) as usize;
+    let last_row = ((top + size).ceil().max(0.0) as usize).min(height);
+    for col in first_col..last_col {
+        if depth.get(col).is_some_and(|&wall| wall < distance) {
+            continue;
+        }
+        let u = (col as f32 + 0.5 - left) / size;
+        if !(0.0..1.0).contains(&u) {
+            continue;
+        }
+        for row in first_row..last_row {
+            let v = (row as f32 + 0.5 - top) / size;
+            if !(0.0..1.0).contains(&v) {
+                continue;
+            }
+            if let Some(index) = sprite.sample_nearest(u, v) {
+                canvas.set_pixel_by_color_index(col as u32, row as u32, shade[index as usize]);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cast_column, draw_sprite, object_sprites, project, rotation, trace, Flats, Renderer,
+        WallHit, WorldSprite,
+    };
+    use crate::camera::WolfCamera;
+    use crate::canvas::Canvas;
+    use crate::colormap::FULL_BRIGHT;
+    use crate::gamedata::GameVariant;
+    use crate::golden::{test_level, test_textures};
+    use crate::level::Level;
+    use crate::mapobject::{parse_objects, Difficulty, Direction};
+    use crate::math::Vec2;
+    use crate::palette::Shift;
+    use crate::texture::Sprite;
+    use crate::wolf_asset::WolfLevel;
+
+    use std::time::Instant;
+
+    #[test]
+    fn rotation_test() {
+        // facing east, viewer in front, behind and to the north
+        assert_eq!(rotation((1.0, 0.0), Vec2::new(3.0, 0.0)), 0);
+        assert_eq!(rotation((1.0, 0.0), Vec2::new(-3.0, 0.0)), 4);
+        assert_eq!(rotation((1.0, 0.0), Vec2::new(0.0, -3.0)), 2);
+        assert_eq!(rotation((0.0, 1.0), Vec2::new(0.0, -3.0)), 4);
+    }
+
+    #[test]
+    fn object_sprites_test() {
+        let header = WolfLevel {
+            width: 4,
+            height: 1,
+            ..Default::default()
+        };
+        // guard facing east (easy), guard facing east (hard), lamp, player start
+        let objects = vec![108, 180, 26, 19];
+        let level = Level::new(
+            &header,
+            [vec![107; 4], objects, vec![0; 4]],
+            GameVariant::Registered,
+        );
+        let sprites = object_sprites(&level, Vec2::new(3.5, 0.5), Difficulty::Easy);
+        assert_eq!(
+            sprites,
+            vec![
+                WorldSprite {
+                    pos: Vec2::new(0.5, 0.5),
+                    sprite: 50
+                },
+                WorldSprite {
+                    pos: Vec2::new(2.5, 0.5),
+                    sprite: 5
+                }
+            ]
+        );
+        assert_eq!(
+            object_sprites(&level, Vec2::new(3.5, 0.5), Difficulty::Hard).len(),
+            3
+        );
+
+        // the same tiles in a Spear of Destiny level have no WL6 sprites
+        let level = Level::new(
+            &header,
+            [vec![107; 4], vec![108, 180, 26, 19], vec![0; 4]],
+            GameVariant::SpearOfDestiny,
+        );
+        assert!(object_sprites(&level, Vec2::new(3.5, 0.5), Difficulty::Hard).is_empty());
+    }
+
+    #[test]
+    fn sprite_depth_test() {
+        let camera = WolfCamera::new(Vec2::new(0.5, 0.5), Vec2::new(1.0, 0.0), 60.0);
+        let (column, depth) = project(&camera, 100, Vec2::new(20.5, 0.5)).unwrap();
+        assert_eq!(column, 50.0);
+        assert!((depth - 20.0).abs() < 1e-4);
+        assert!(project(&camera, 100, Vec2::new(-3.0, 0.5)).is_none());
+        let wide = WolfCamera::new(Vec2::new(0.5, 0.5), Vec2::new(1.0, 0.0), 90.0);
+        let (column, _) = project(&wide, 100, Vec2::new(10.5, 5.5)).unwrap();
+        assert!((column - 75.0).abs() < 1e-3);
+
+        // a fully opaque sprite behind a wall on the left half of the screen
+        let mut canvas = Canvas::new((100, 100));
+        canvas.clear();
+        let sprite = Sprite::new(vec![4u8; 64 * 64], vec![true; 64 * 64], 64, 64);
+        let mut zbuffer = vec![f32::MAX; 100];
+        zbuffer[..50].iter_mut().for_each(|d| *d = 1.0);
+        // 40 pixels wide
+        draw_sprite(&mut canvas, &sprite, 50.0, 25.0, 1000.0, &zbuffer, &FULL_BRIGHT);
+
+        let buffer = canvas.buffer_as_mut();
+        let pixel = |x: usize, y: usize| buffer[(y * 100 + x) * 4];
+        let red = crate::palette::WOLF_PALETTE[4].0;
+        assert_eq!(pixel(45, 50), 0xFF);
+        assert_eq!(pixel(55, 50), red);
+        assert_eq!(pixel(69, 50), red);
+        assert_eq!(pixel(71, 50), 0xFF);
+        assert_eq!(pixel(55, 29), 0xFF);
+    }
+
+    /// 5x3 level: a corridor along y = 1 closed by a vertical door at x = 2
+    fn door_level() -> Level {
+        let header = WolfLevel {
+            width: 5,
+            height: 3,
+            ..Default::default()
+        };
+        #[rustfmt::skip]
+        let walls = vec![
+            1, 1, 2, 1, 1,
+            1, 107, 90, 107, 3,
+            1, 1, 2, 1, 1,
+        ];
+        Level::new(&header, [walls, vec![0; 15], vec![0; 15]], GameVariant::Registered)
+    }
+
+    #[test]
+    fn door_trace_test() {
+        let mut level = door_level();
+        let origin = Vec2::new(1.5, 1.25);
+        let hit = trace(&level, 100, origin, 0.0).unwrap();
+        assert_eq!(
+            hit,
+            WallHit {
+                t: 1.0,
+                u: 0.25,
+                page: 101
+            }
+        );
+
+        // half open, the upper half of the tile is passable
+        level.door_mut(2, 1).unwrap().open = 0.5;
+        let hit = trace(&level, 100, origin, 0.0).unwrap();
+        assert_eq!(hit.page, 5);
+        assert_eq!(hit.t, 2.5);
+        let hit = trace(&level, 100, Vec2::new(1.5, 1.75), 0.0).unwrap();
+        assert_eq!((hit.t, hit.u, hit.page), (1.0, 0.25, 101));
+
+        // the jamb seen from inside the open doorway
+        level.door_mut(2, 1).unwrap().open = 1.0;
+        let hit = trace(
+            &level,
+            100,
+            Vec2::new(2.5, 1.5),
+            -std::f32::consts::FRAC_PI_2,
+        )
+        .unwrap();
+        assert_eq!(hit.page, 102);
+        assert!((hit.t - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn pushwall_trace_test() {
+        let mut level = door_level();
+        // the wall east of the corridor slides back into the corridor's end
+        level.walls[5 + 3] = 4;
+        level.objects = parse_objects(&[0, 0, 0, 0, 0, 0, 0, 0, 98, 0], 5, GameVariant::Registered);
+        assert!(!level.push_wall(3, 1, Direction::East));
+
+        level.walls[5 + 4] = 107;
+        assert!(level.push_wall(3, 1, Direction::East));
+        level.move_pushwall(16);
+        level.door_mut(2, 1).unwrap().open = 1.0;
+        let hit = trace(&level, 100, Vec2::new(1.5, 1.5), 0.0).unwrap();
+        assert_eq!(hit.page, 7);
+        assert!((hit.t - 1.75).abs() < 1e-4);
+        assert!((hit.u - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn flat_wall_test() {
+        // an empty 20x20 room, the camera looks straight at its east wall
+        let header = WolfLevel {
+            width: 20,
+            height: 20,
+            ..Default::default()
+        };
+        let walls = (0..400)
+            .map(|i| {
+                let (x, y) = (i % 20, i / 20);
+                if x == 0 || y == 0 || x == 19 || y == 19 {
+                    1
+                } else {
+                    107
+                }
+            })
+            .collect::<Vec<u16>>();
+        let planes = [walls, vec![0; 400], vec![0; 400]];
+        let level = Level::new(&header, planes, GameVariant::Registered);
+        let camera = WolfCamera::new(Vec2::new(2.5, 10.5), Vec2::new(1.0, 0.0), 45.0);
+        let focal = camera.focal_length(320);
+        for col in 0..320 {
+            let (hit, distance) = cast_column(&level, &camera, 100, 320, col).unwrap();
+            assert_eq!(hit.page, 1);
+            assert!((distance - 16.5).abs() < 1e-3, "column {}: {}", col, distance);
+            assert_eq!((focal / distance) as usize, (focal / 16.5) as usize);
+        }
+    }
+
+    #[test]
+    fn parallel_render_test() {
+        let level = test_level();
+        let camera = WolfCamera::new(Vec2::new(3.5, 8.5), Vec2::new(1.0, -0.25), 60.0);
+        let mut renderer = Renderer::new(test_textures());
+        let parallel = renderer.render_frame(&level, &camera, (203, 117)).unwrap();
+        renderer.parallel = false;
+        let serial = renderer.render_frame(&level, &camera, (203, 117)).unwrap();
+        assert!(parallel.buffer() == serial.buffer());
+    }
+
+    #[test]
+    fn grid_line_render_test() {
+        // rays along a half-tile coordinate hit walls exactly on a grid line
+        let level = test_level();
+        let camera = WolfCamera::new(Vec2::new(3.5, 8.0), Vec2::new(-1.0, 0.0), 60.0);
+        let mut renderer = Renderer::new(test_textures());
+        renderer.render_frame(&level, &camera, (161, 200)).unwrap();
+        let camera = WolfCamera::new(Vec2::new(3.0, 8.5), Vec2::new(0.0, 1.0), 60.0);
+        renderer.render_frame(&level, &camera, (161, 200)).unwrap();
+        renderer.flats = Some(Flats {
+            floor: 4,
+            ceiling: 6,
+        });
+        renderer.render_frame(&level, &camera, (161, 200)).unwrap();
+    }
+
+    #[test]
+    fn shifted_render_test() {
+        let level = test_level();
+        let camera = WolfCamera::new(Vec2::new(3.5, 8.5), Vec2::new(1.0, -0.25), 60.0);
+        let mut renderer = Renderer::new(test_textures());
+        let mut canvas = Canvas::new((64, 40));
+        canvas.set_shift(Some(Shift {
+            color: (255, 0, 0),
+            amount: 1.0,
+        }));
+        renderer.render(&level, &camera, &mut canvas).unwrap();
+        assert!(canvas.buffer().chunks_exact(4).all(|pixel| pixel == [255, 0, 0, 255]));
+        canvas.set_shift(None);
+        renderer.render(&level, &camera, &mut canvas).unwrap();
+        assert!(canvas.buffer().chunks_exact(4).any(|pixel| pixel != [255, 0, 0, 255]));
+    }
+
+    /// Frame times of the single and multi threaded renderer, run with
+    /// `cargo test --release frame_time_benchmark -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn frame_time_benchmark() {
+        const FRAMES: u32 = 50;
+        let level = test_level();
+        let camera = WolfCamera::new(Vec2::new(3.5, 8.5), Vec2::new(1.0, -0.25), 60.0);
+        let mut renderer = Renderer::new(test_textures());
+        for &resolution in &[(320, 200), (640, 400), (1920, 1080)] {
+            let mut canvas = Canvas::new(resolution);
+            let mut frame_time = |parallel| {
+                renderer.parallel = parallel;
+                renderer.render(&level, &camera, &mut canvas).unwrap();
+                let start = Instant::now();
+                for _ in 0..FRAMES {
+                    renderer.render(&level, &camera, &mut canvas).unwrap();
+                }
+                start.elapsed() / FRAMES
+            };
+            let serial = frame_time(false);
+            let parallel = frame_time(true);
+            println!(
+                "{}x{}: {:?} single threaded, {:?} on {} threads, {:.2}x",
+                resolution.0,
+                resolution.1,
+                serial,
+                parallel,
+                rayon::current_num_threads(),
+                serial.as_secs_f64() / parallel.as_secs_f64()
+            );
+        }
+    }
+}
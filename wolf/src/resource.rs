@@ -1,13 +1,16 @@
-use sdl2::render::Texture;
-
+use crate::gamedata::{GameData, GameVariant};
 use crate::io::{app_root_dir, asset_file};
+use crate::level::{ceiling_color, Level};
+use crate::mapobject::{parse_objects, MapObject};
 use crate::wolf_asset::{
-    read_atlas, read_level, read_map, read_texture, WolfLevel, WolfMapAtlas, WolfVSWAP, read_vswap
+    read_atlas, read_level, read_map, read_sprite, read_texture, WolfAssetError, WolfLevel, WolfMapAtlas,
+    WolfVSWAP, read_vswap, Result
 };
 use std::cell::{Ref, RefCell, RefMut};
 use std::fs::File;
 
-use crate::texture::{From2DData, Texture2D};
+use crate::texture::{From2DData, Sprite, Texture2D};
+use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 
 pub struct Map {
@@ -21,107 +24,237 @@ pub struct MapCache {
     map_head: std::fs::File,             // map file handle
     wolf_level: WolfLevel,               // map file level info, needed by the file reading  routine
     cur_level_index: Option<(u32, u32)>, // (episode, level)
+    variant: GameVariant,                // picks the ceiling colours, level numbering and statics
 }
 
 pub struct TexturePool {
     texture_pool: HashMap<usize, Texture2D<u8>>,
+    sprite_pool: HashMap<usize, Sprite>,
     vswap_head: WolfVSWAP,
-    vswap_file: std::fs::File
+    vswap_file: Option<std::fs::File>, // None when the pages were given in memory
 }
 
 impl TexturePool {
-    pub fn open() -> Self {
-        let mut vswap_file = asset_file("resources/original/VSWAP.WL6").unwrap();
-        let vswap_header = read_vswap(&mut vswap_file);
-        TexturePool {
+    pub fn open(data: &GameData) -> Result<Self> {
+        let mut vswap_file = File::open(&data.files.vswap)
+            .map_err(|e| WolfAssetError::Io { offset: 0, source: e })?;
+        let vswap_header = read_vswap(&mut vswap_file)?;
+        Ok(TexturePool {
             texture_pool: HashMap::new(),
+            sprite_pool: HashMap::new(),
             vswap_head:vswap_header,
-            vswap_file:vswap_file
+            vswap_file:Some(vswap_file)
+        })
+    }
+
+    /// A pool over pages already in memory: `walls` are the pages before the first
+    /// sprite, the last 8 of them the door pages. Used where no VSWAP is at hand
+    pub fn from_pages(walls: Vec<Texture2D<u8>>, sprites: Vec<Sprite>) -> Self {
+        let vswap_head = WolfVSWAP {
+            chunck_num: (walls.len() + sprites.len()) as i16,
+            sprite_start: walls.len() as i16,
+            sound_start: (walls.len() + sprites.len()) as i16,
+            chunk: Vec::new(),
+        };
+        TexturePool {
+            texture_pool: walls.into_iter().enumerate().collect(),
+            sprite_pool: sprites.into_iter().enumerate().collect(),
+            vswap_head: vswap_head,
+            vswap_file: None,
         }
     }
-    pub fn get_texture(
-        &mut self,
-        texture_id: usize,
-    ) -> &Texture2D<u8> {
-        let mut file = &mut self.vswap_file;
-        let head =&self.vswap_head;
-        self.texture_pool
-            .entry(texture_id)
-            .or_insert_with_key(|&key| {
-                read_texture(&mut file, &head, key).map_or_else(
-                    |err| {
-                        println!("{}", err);
-                        From2DData::from_data(vec![0u8; 64 * 64], 64, 64, 3)
-                    },
-                    |tex| From2DData::from_data(tex, 64, 64, 3),
-                )
-            })
+
+    /// A wall page of VSWAP. VSWAP stores walls column by column, the page comes
+    /// back transposed to rows so that `data[y * 64 + x]` is the texel at column `x`,
+    /// row `y`, like every other texture. A page that fails to load is not cached
+    pub fn get_texture(&mut self, texture_id: usize) -> Result<&Texture2D<u8>> {
+        let count = self.vswap_head.sprite_start.max(0) as usize;
+        match self.texture_pool.entry(texture_id) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let page = match self.vswap_file.as_mut() {
+                    Some(file) => read_texture(file, &self.vswap_head, texture_id)?,
+                    None => {
+                        return Err(WolfAssetError::ChunkOutOfRange {
+                            index: texture_id,
+                            count: count,
+                        })
+                    }
+                };
+                Ok(entry.insert(From2DData::from_data(wall_rows(&page), 64, 64, 3)))
+            }
+        }
+    }
+
+    /// A page already loaded by `get_texture`, shared reads need no file access
+    pub fn texture(&self, texture_id: usize) -> Option<&Texture2D<u8>> {
+        self.texture_pool.get(&texture_id)
+    }
+
+    /// First of the door pages, they sit right before the sprites: door, door side,
+    /// elevator door and locked door, each as a horizontal and vertical variant
+    pub fn door_wall(&self) -> usize {
+        (self.vswap_head.sprite_start.max(8) - 8) as usize
+    }
+
+    /// `sprite_id` counts from the first sprite page of VSWAP. A sprite that fails
+    /// to load is not cached, the next call reads it again
+    pub fn get_sprite(&mut self, sprite_id: usize) -> Result<&Sprite> {
+        let (sprite_start, sound_start) = (self.vswap_head.sprite_start, self.vswap_head.sound_start);
+        let count = (sound_start as i32 - sprite_start as i32).max(0) as usize;
+        match self.sprite_pool.entry(sprite_id) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => {
+                let (data, mask) = match self.vswap_file.as_mut() {
+                    Some(file) => read_sprite(file, &self.vswap_head, sprite_id)?,
+                    None => {
+                        return Err(WolfAssetError::ChunkOutOfRange {
+                            index: sprite_id,
+                            count: count,
+                        })
+                    }
+                };
+                Ok(entry.insert(Sprite::new(data, mask, 64, 64)))
+            }
+        }
     }
 }
 
+/// Wall pages are stored column by column, textures are sampled row by row
+fn wall_rows(page: &[u8]) -> Vec<u8> {
+    let mut rows = vec![0u8; 64 * 64];
+    for (index, &texel) in page.iter().take(64 * 64).enumerate() {
+        rows[(index % 64) * 64 + index / 64] = texel;
+    }
+    rows
+}
+
 impl MapCache {
-    pub fn load() -> MapCache {
-        let mut atlas_dir = app_root_dir().unwrap();
-        atlas_dir.push("resources/original/MAPHEAD.WL6");
-        let atlas = read_atlas(atlas_dir);
-        let map_head = asset_file("resources/original/GAMEMAPS.WL6").unwrap();
-        MapCache {
+    pub fn load(data: &GameData) -> Result<MapCache> {
+        let atlas = read_atlas(&data.files.map_head)?;
+        let map_head = File::open(&data.files.game_maps)
+            .map_err(|e| WolfAssetError::Io { offset: 0, source: e })?;
+        Ok(MapCache {
             atlas: atlas,
             map_head: map_head,
             wolf_level: Default::default(),
             cur_level_index: None,
-        }
+            variant: data.variant,
+        })
     }
 
-    pub fn read_map(&mut self, episode: u32, level: u32, map: i32) -> Map {
+    fn select_level(&mut self, episode: u32, level: u32) -> Result<()> {
         let new_level_index = (episode, level);
-        match self.cur_level_index {
-            Some(index) => {
-                if index != new_level_index {
-                    self.wolf_level = read_level(
-                        &self.atlas,
-                        &mut self.map_head,
-                        episode as i32,
-                        level as i32,
-                    );
-                }
-            }
-            None => {
-                self.wolf_level = read_level(
-                    &self.atlas,
-                    &mut self.map_head,
-                    episode as i32,
-                    level as i32,
-                );
-            }
+        if self.cur_level_index != Some(new_level_index) {
+            // forget the cached header first so a failed read is not taken for this level
+            self.cur_level_index = None;
+            self.wolf_level = read_level(
+                &self.atlas,
+                &mut self.map_head,
+                self.variant.levels_per_episode(),
+                episode as i32,
+                level as i32,
+            )?;
         }
 
         self.cur_level_index = Some(new_level_index);
+        Ok(())
+    }
+
+    pub fn read_map(&mut self, episode: u32, level: u32, map: i32) -> Result<Map> {
+        self.select_level(episode, level)?;
         let map_data = read_map(
             &self.atlas,
             &self.wolf_level,
             &mut self.map_head,
             map as usize,
-        );
-        Map {
+        )?;
+        Ok(Map {
             data: map_data,
             width: 64,
             height: 64,
-        }
+        })
+    }
+
+    /// Objects placed on the level, decoded from plane 1
+    pub fn read_objects(&mut self, episode: u32, level: u32) -> Result<Vec<MapObject>> {
+        let map = self.read_map(episode, level, 1)?;
+        Ok(parse_objects(&map.data, map.width as usize, self.variant))
+    }
+
+    /// All three planes of a level together with its name, size and ceiling colour
+    pub fn read_level(&mut self, episode: u32, level: u32) -> Result<Level> {
+        self.select_level(episode, level)?;
+        let mut plane = |index| read_map(&self.atlas, &self.wolf_level, &mut self.map_head, index);
+        let planes = [plane(0)?, plane(1)?, plane(2)?];
+        let mut map = Level::new(&self.wolf_level, planes, self.variant);
+        map.ceiling = ceiling_color(self.variant, episode, level);
+        Ok(map)
     }
 }
 
 
 #[cfg(test)]
 mod resource_test{
-    use super::TexturePool;
+    use super::{wall_rows, TexturePool};
+    use crate::gamedata::GameData;
+    use crate::io::app_root_dir;
+    use crate::texture::{From2DData, Sprite};
+    use crate::wolf_asset::WolfAssetError;
 
     #[test]
     fn texture_pool_test(){
-        let mut tp = TexturePool::open();
+        let data = GameData::discover(app_root_dir().unwrap().join("resources/original")).unwrap();
+        let mut tp = TexturePool::open(&data).unwrap();
         for tid in 0..1000{
             let t = tp.get_texture(tid);
-            // println!("{:?}", t.buffer());
+            // println!("{:?}", t.map(|t| t.buffer()));
         }
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn wall_rows_test() {
+        // every texel of a column holds its column number
+        let page = (0..64 * 64).map(|index| (index / 64) as u8).collect::<Vec<u8>>();
+        let rows = wall_rows(&page);
+        assert_eq!(&rows[..4], &[0, 1, 2, 3]);
+        assert_eq!(rows[10 * 64 + 63], 63);
+        assert!(rows.chunks(64).all(|row| row == &rows[..64]));
+    }
+
+    #[test]
+    fn missing_sprite_test() {
+        let sprites = vec![Sprite::empty(64, 64), Sprite::empty(64, 64)];
+        let mut tp = TexturePool::from_pages(Vec::new(), sprites);
+        assert!(tp.get_sprite(1).is_ok());
+        assert!(matches!(
+            tp.get_sprite(5),
+            Err(WolfAssetError::ChunkOutOfRange { index: 5, count: 2 })
+        ));
+    }
+
+    #[test]
+    fn missing_texture_test() {
+        let walls = vec![From2DData::from_data(vec![0u8; 64 * 64], 64, 64, 3)];
+        let mut tp = TexturePool::from_pages(walls, Vec::new());
+        assert!(tp.get_texture(0).is_ok());
+        assert!(matches!(
+            tp.get_texture(3),
+            Err(WolfAssetError::ChunkOutOfRange { index: 3, count: 1 })
+        ));
+        // the failure is not cached as a blank page
+        assert!(tp.texture(3).is_none());
+    }
+
+    #[test]
+    fn malformed_sprite_range_test() {
+        // a VSWAP header whose sprites would start far past its sounds
+        let mut tp = TexturePool::from_pages(Vec::new(), Vec::new());
+        tp.vswap_head.sprite_start = i16::MAX;
+        tp.vswap_head.sound_start = i16::MIN;
+        assert!(matches!(
+            tp.get_sprite(0),
+            Err(WolfAssetError::ChunkOutOfRange { index: 0, count: 0 })
+        ));
+    }
+}
@@ -30,15 +30,62 @@ impl<T> Texture2D<T> where T:Primitive{
     pub fn buffer(&self)->&Vec<T> {
         &self.data
     }
+	/// `u` and `v` are clamped to the texture, 1.0 reads the last texel
 	#[inline(always)]
 	pub fn sample_nearest(&self, u:f32,v:f32)->T{
-		let ix = (u * self.width as f32) as usize;
-		let iy = (v * self.height as f32) as usize;
+		let (ix, iy) = texel(u, v, self.width, self.height);
 		let index= ix + iy * self.width as usize;
 		self.data[index]
 	}
 }
 
+/// A texture with a per-pixel transparency mask, used for sprites
+pub struct Sprite {
+    pub texture: Texture2D<u8>,
+    pub mask: Vec<bool>, // true for opaque pixels
+}
+
+impl Sprite {
+    pub fn new(data: Vec<u8>, mask: Vec<bool>, width: u32, height: u32) -> Self {
+        Sprite {
+            texture: From2DData::from_data(data, width, height, 1),
+            mask: mask,
+        }
+    }
+
+    /// a sprite without any opaque pixel
+    pub fn empty(width: u32, height: u32) -> Self {
+        let size = (width * height) as usize;
+        Sprite::new(vec![0u8; size], vec![false; size], width, height)
+    }
+
+    #[inline(always)]
+    pub fn is_opaque(&self, index: usize) -> bool {
+        self.mask[index]
+    }
+
+    /// returns None for transparent pixels, `u` and `v` are clamped like for textures
+    #[inline(always)]
+    pub fn sample_nearest(&self, u: f32, v: f32) -> Option<u8> {
+        let (ix, iy) = texel(u, v, self.texture.width, self.texture.height);
+        let index = ix + iy * self.texture.width as usize;
+        if self.mask[index] {
+            Some(self.texture.data[index])
+        } else {
+            None
+        }
+    }
+}
+
+/// Nearest texel to `u`, `v`. Coordinates that round to the far edge, like 1.0 - u
+/// for a hit right on a grid line, stay on the last row and column
+#[inline(always)]
+fn texel(u: f32, v: f32, width: u32, height: u32) -> (usize, usize) {
+    let ix = ((u * width as f32) as usize).min(width.saturating_sub(1) as usize);
+    let iy = ((v * height as f32) as usize).min(height.saturating_sub(1) as usize);
+    (ix, iy)
+}
+
 pub trait From2DData<U>{
     fn from_data(
         data: Vec<U>,
@@ -0,0 +1,424 @@
+use crate::palette::{parse_palette, Palette};
+use crate::texture::{From2DData, Texture2D};
+use crate::wolf_asset::{le_i16, le_i32, le_u16, Result, WolfAssetError};
+
+use std::fs;
+use std::path::Path;
+
+/// Number of nodes in the VGADICT Huffman tree, the last one is the root
+const HUFF_NODES: usize = 255;
+const HUFF_ROOT: usize = 254;
+/// VGAHEAD entry marking a chunk that is not present in VGAGRAPH
+const SPARSE_CHUNK: u32 = 0x00FF_FFFF;
+const FONT_GLYPHS: usize = 256;
+
+/// Where fonts and pictures live in the chunk list, differs between game versions
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct VgaLayout {
+    pub start_font: usize,
+    pub num_fonts: usize,
+    pub start_pics: usize,
+    pub num_pics: usize,
+}
+
+/// Chunk layout of Wolfenstein 3D 1.4 registered (gfxv_wl6.h)
+pub const WL6_VGA_LAYOUT: VgaLayout = VgaLayout {
+    start_font: 1,
+    num_fonts: 2,
+    start_pics: 3,
+    num_pics: 132,
+};
+
+pub struct Font {
+    pub height: u32,
+    pub glyphs: Vec<Texture2D<u8>>, // one per character code, zero width for missing ones
+}
+
+pub struct VgaGraph {
+    offsets: Vec<Option<u32>>, // None for sparse chunks
+    dict: Vec<(u16, u16)>,     // (bit0, bit1) per node
+    graph: Vec<u8>,
+    pic_sizes: Vec<(u16, u16)>,
+    layout: VgaLayout,
+}
+
+impl VgaGraph {
+    pub fn open<P: AsRef<Path>>(head: P, dict: P, graph: P, layout: VgaLayout) -> Result<Self> {
+        let read = |path: P| {
+            fs::read(path).map_err(|e| WolfAssetError::Io {
+                offset: 0,
+                source: e,
+            })
+        };
+        let head = read(head)?;
+        let dict = read(dict)?;
+        let graph = read(graph)?;
+        VgaGraph::parse(&head, &dict, graph, layout)
+    }
+
+    /// Parses the content of VGAHEAD, VGADICT and VGAGRAPH, and reads the picture table
+    /// from the first chunk
+    pub fn parse(head: &[u8], dict: &[u8], graph: Vec<u8>, layout: VgaLayout) -> Result<Self> {
+        // VGAHEAD: 3-byte offsets, the last one is the end of VGAGRAPH
+        let offsets = head
+            .chunks_exact(3)
+            .enumerate()
+            .map(|(chunk, entry)| {
+                let offset = u32::from_le_bytes([entry[0], entry[1], entry[2], 0]);
+                if offset == SPARSE_CHUNK {
+                    Ok(None)
+                } else if offset as usize > graph.len() {
+                    Err(WolfAssetError::BadChunkOffset {
+                        chunk,
+                        offset: offset as u64,
+                    })
+                } else {
+                    Ok(Some(offset))
+                }
+            })
+            .collect::<Result<Vec<Option<u32>>>>()?;
+
+        if dict.len() < HUFF_NODES * 4 {
+            return Err(WolfAssetError::TruncatedHeader {
+                offset: 0,
+                expected: HUFF_NODES * 4,
+            });
+        }
+        let dict = (0..HUFF_NODES)
+            .map(|node| (le_u16(dict, node * 4), le_u16(dict, node * 4 + 2)))
+            .collect();
+
+        let mut vga = VgaGraph {
+            offsets: offsets,
+            dict: dict,
+            graph: graph,
+            pic_sizes: Vec::new(),
+            layout: layout,
+        };
+        let table = vga.chunk(0)?;
+        vga.pic_sizes = table
+            .chunks_exact(4)
+            .take(layout.num_pics)
+            .map(|entry| {
+                (
+                    le_i16(entry, 0).max(0) as u16,
+                    le_i16(entry, 2).max(0) as u16,
+                )
+            })
+            .collect();
+        Ok(vga)
+    }
+
+    /// number of chunks in VGAGRAPH, sparse ones included
+    pub fn chunk_count(&self) -> usize {
+        self.offsets.len().saturating_sub(1)
+    }
+
+    pub fn layout(&self) -> VgaLayout {
+        self.layout
+    }
+
+    /// Expands chunk `index`. A chunk starts with its expanded length as i32,
+    /// followed by the Huffman compressed data
+    pub fn chunk(&self, index: usize) -> Result<Vec<u8>> {
+        if index >= self.chunk_count() {
+            return Err(WolfAssetError::ChunkOutOfRange {
+                index,
+                count: self.chunk_count(),
+            });
+        }
+        let start = self.offsets[index].ok_or(WolfAssetError::BadChunkOffset {
+            chunk: index,
+            offset: SPARSE_CHUNK as u64,
+        })? as usize;
+        // compressed data runs up to the next chunk that is present
+        let end = self.offsets[index + 1..]
+            .iter()
+            .find_map(|offset| *offset)
+            .map_or(self.graph.len(), |offset| offset as usize);
+        if end < start + 4 {
+            return Err(WolfAssetError::TruncatedChunk {
+                offset: start as u64,
+            });
+        }
+        let length = le_i32(&self.graph, start).max(0) as usize;
+        huff_expand(&self.graph[start + 4..end], &self.dict, length)
+            .map_err(|e| e.relocate(|offset| start as u64 + 4 + offset))
+    }
+
+    /// (width, height) of picture `index`, counted from the first picture chunk
+    pub fn pic_size(&self, index: usize) -> Option<(u16, u16)> {
+        self.pic_sizes.get(index).copied()
+    }
+
+    pub fn pic_count(&self) -> usize {
+        self.pic_sizes.len()
+    }
+
+    /// Picture `index`, counted from the first picture chunk, as row major color indices
+    pub fn picture(&self, index: usize) -> Result<Texture2D<u8>> {
+        let (width, height) = self
+            .pic_size(index)
+            .ok_or(WolfAssetError::ChunkOutOfRange {
+                index,
+                count: self.pic_count(),
+            })?;
+        let chunk = self.layout.start_pics + index;
+        let data = self.chunk(chunk)?;
+        let pixels = deplanarize(&data, width as usize, height as usize).ok_or(
+            WolfAssetError::TruncatedChunk {
+                offset: self.offsets[chunk].unwrap_or(0) as u64,
+            },
+        )?;
+        Ok(From2DData::from_data(
+            pixels,
+            width as u32,
+            height as u32,
+            1,
+        ))
+    }
+
+    /// Palette stored in chunk `index`, Spear of Destiny keeps the palettes of its
+    /// title and end screens in VGAGRAPH
+    pub fn palette(&self, index: usize) -> Result<Palette> {
+        let data = self.chunk(index)?;
+        parse_palette(&data).map_err(|e| e.relocate(|_| self.offsets[index].unwrap_or(0) as u64))
+    }
+
+    /// Font `index`, counted from the first font chunk
+    pub fn font(&self, index: usize) -> Result<Font> {
+        if index >= self.layout.num_fonts {
+            return Err(WolfAssetError::ChunkOutOfRange {
+                index,
+                count: self.layout.num_fonts,
+            });
+        }
+        let chunk = self.layout.start_font + index;
+        let data = self.chunk(chunk)?;
+        parse_font(&data).map_err(|e| e.relocate(|_| self.offsets[chunk].unwrap_or(0) as u64))
+    }
+}
+
+/// Expands `length` bytes of Huffman coded `source`.
+///
+/// Bits are read from the lowest of each byte, starting at the root node. A node value
+/// below 256 is a decoded byte, otherwise it is the index of the next node + 256.
+/// Error offsets are relative to the start of `source`
+pub fn huff_expand(source: &[u8], dict: &[(u16, u16)], length: usize) -> Result<Vec<u8>> {
+    if dict.len() < HUFF_NODES {
+        return Err(WolfAssetError::TruncatedHeader {
+            offset: 0,
+            expected: HUFF_NODES * 4,
+        });
+    }
+    let mut dest = Vec::with_capacity(length);
+    let mut node = HUFF_ROOT;
+    let mut read = 0usize;
+    while dest.len() < length {
+        let byte = *source.get(read).ok_or(WolfAssetError::TruncatedChunk {
+            offset: read as u64,
+        })?;
+        for bit in 0..8 {
+            let (bit0, bit1) = dict[node];
+            let value = if byte & (1 << bit) == 0 { bit0 } else { bit1 };
+            if value < 256 {
+                dest.push(value as u8);
+                node = HUFF_ROOT;
+                if dest.len() == length {
+                    break;
+                }
+            } else if (value as usize - 256) < dict.len() {
+                node = value as usize - 256;
+            } else {
+                return Err(WolfAssetError::BadHuffmanNode {
+                    offset: read as u64,
+                    node: value,
+                });
+            }
+        }
+        read += 1;
+    }
+    Ok(dest)
+}
+
+/// Converts a picture stored as four planes, one for every x % 4, to row major pixels
+pub fn deplanarize(data: &[u8], width: usize, height: usize) -> Option<Vec<u8>> {
+    let quarter = width / 4;
+    if data.len() < width * height {
+        return None;
+    }
+    let plane_size = quarter * height;
+    let mut pixels = vec![0u8; width * height];
+    for y in 0..height {
+        for x in 0..width {
+            pixels[y * width + x] = data[(x % 4) * plane_size + y * quarter + x / 4];
+        }
+    }
+    Some(pixels)
+}
+
+/// A font chunk is the glyph height, 256 glyph offsets, 256 glyph widths and
+/// one byte per pixel for every glyph
+fn parse_font(data: &[u8]) -> Result<Font> {
+    let header = 2 + FONT_GLYPHS * 3;
+    if data.len() < header {
+        return Err(WolfAssetError::TruncatedHeader {
+            offset: 0,
+            expected: header,
+        });
+    }
+    let height = le_i16(data, 0).max(0) as usize;
+    let glyphs = (0..FONT_GLYPHS)
+        .map(|c| {
+            let location = le_i16(data, 2 + c * 2).max(0) as usize;
+            let width = data[2 + FONT_GLYPHS * 2 + c] as usize;
+            let end = location + width * height;
+            if width > 0 && end > data.len() {
+                return Err(WolfAssetError::TruncatedChunk {
+                    offset: location as u64,
+                });
+            }
+            let pixels = if width > 0 {
+                data[location..end].to_vec()
+            } else {
+                Vec::new()
+            };
+            Ok(From2DData::from_data(
+                pixels,
+                width as u32,
+                height as u32,
+                1,
+            ))
+        })
+        .collect::<Result<Vec<Texture2D<u8>>>>()?;
+    Ok(Font {
+        height: height as u32,
+        glyphs: glyphs,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{deplanarize, huff_expand, VgaGraph, VgaLayout, HUFF_NODES};
+
+    /// A balanced tree where every byte is coded by its own 8 bits, most significant first.
+    /// Heap node k has children 2k and 2k+1 and is stored at index 255 - k
+    fn balanced_dict() -> Vec<(u16, u16)> {
+        let value = |child: usize| {
+            if child >= 256 {
+                (child - 256) as u16
+            } else {
+                (256 + 255 - child) as u16
+            }
+        };
+        (0..HUFF_NODES)
+            .map(|index| {
+                let k = 255 - index;
+                (value(2 * k), value(2 * k + 1))
+            })
+            .collect()
+    }
+
+    fn huff_compress(data: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        let mut bit = 0;
+        for &byte in data {
+            for shift in (0..8).rev() {
+                if bit % 8 == 0 {
+                    out.push(0u8);
+                }
+                if byte & (1 << shift) != 0 {
+                    *out.last_mut().unwrap() |= 1 << (bit % 8);
+                }
+                bit += 1;
+            }
+        }
+        out
+    }
+
+    fn graph_chunk(data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as i32).to_le_bytes().to_vec();
+        chunk.extend(huff_compress(data));
+        chunk
+    }
+
+    #[test]
+    fn huff_expand_test() {
+        let dict = balanced_dict();
+        let data: Vec<u8> = (0..=255u8).chain(0..10).collect();
+        assert_eq!(
+            huff_expand(&huff_compress(&data), &dict, data.len()).unwrap(),
+            data
+        );
+        assert!(huff_expand(&huff_compress(&data[..4]), &dict, 5).is_err());
+    }
+
+    #[test]
+    fn deplanarize_test() {
+        // 8x2, plane p holds the pixels with x % 4 == p
+        let planar = [0, 4, 8, 12, 1, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15];
+        let pixels = deplanarize(&planar, 8, 2).unwrap();
+        assert_eq!(pixels, (0..16).collect::<Vec<u8>>());
+        assert!(deplanarize(&planar[..15], 8, 2).is_none());
+    }
+
+    #[test]
+    fn vgagraph_test() {
+        // chunk 0: pictable, chunk 1: font, chunk 2: sparse, chunk 3: picture
+        let layout = VgaLayout {
+            start_font: 1,
+            num_fonts: 1,
+            start_pics: 3,
+            num_pics: 1,
+        };
+        let pictable = [8u8, 0, 2, 0];
+        let mut font = vec![0u8; 2 + 256 * 3];
+        font[0] = 2; // height
+        let glyph_at = font.len() as i16;
+        font[2 + b'A' as usize * 2..4 + b'A' as usize * 2].copy_from_slice(&glyph_at.to_le_bytes());
+        font[2 + 512 + b'A' as usize] = 3; // width
+        font.extend_from_slice(&[1, 0, 1, 0, 1, 0]);
+        let picture = [0u8, 4, 8, 12, 1, 5, 9, 13, 2, 6, 10, 14, 3, 7, 11, 15];
+
+        let chunks = [
+            graph_chunk(&pictable),
+            graph_chunk(&font),
+            graph_chunk(&picture),
+        ];
+        let mut graph = Vec::new();
+        let mut head = Vec::new();
+        let push_offset = |head: &mut Vec<u8>, offset: u32| {
+            head.extend_from_slice(&offset.to_le_bytes()[..3]);
+        };
+        for (index, chunk) in chunks.iter().enumerate() {
+            if index == 2 {
+                push_offset(&mut head, 0xFFFFFF);
+            }
+            push_offset(&mut head, graph.len() as u32);
+            graph.extend_from_slice(chunk);
+        }
+        push_offset(&mut head, graph.len() as u32);
+        let dict: Vec<u8> = balanced_dict()
+            .iter()
+            .flat_map(|(b0, b1)| [b0.to_le_bytes(), b1.to_le_bytes()].concat())
+            .collect();
+
+        let vga = VgaGraph::parse(&head, &dict, graph, layout).unwrap();
+        assert_eq!(vga.chunk_count(), 4);
+        assert_eq!(vga.pic_size(0), Some((8, 2)));
+        assert!(vga.chunk(2).is_err());
+        // the picture table is no palette
+        assert!(vga.palette(0).is_err());
+
+        let pic = vga.picture(0).unwrap();
+        assert_eq!((pic.width, pic.height), (8, 2));
+        assert_eq!(pic.data, (0..16).collect::<Vec<u8>>());
+
+        let font = vga.font(0).unwrap();
+        assert_eq!(font.height, 2);
+        let a = &font.glyphs[b'A' as usize];
+        assert_eq!((a.width, a.height), (3, 2));
+        assert_eq!(a.data, vec![1, 0, 1, 0, 1, 0]);
+        assert_eq!(font.glyphs[b'B' as usize].width, 0);
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs::File;
+use std::io::{BufWriter, Result, Write};
+use std::path::Path;
+
+/// Writes the RIFF header of a mono PCM WAV file holding `data_bytes` bytes of samples.
+/// The RIFF size counts the pad byte that follows an odd `data` chunk, the `data`
+/// size does not
+fn write_header<W: Write>(
+    writer: &mut W,
+    data_bytes: u32,
+    sample_rate: u32,
+    bits_per_sample: u16,
+) -> Result<()> {
+    let block_align = bits_per_sample / 8;
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_bytes + (data_bytes & 1)).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&1u16.to_le_bytes())?; // PCM
+    writer.write_all(&1u16.to_le_bytes())?; // mono
+    writer.write_all(&sample_rate.to_le_bytes())?;
+    writer.write_all(&(sample_rate * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&bits_per_sample.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_bytes.to_le_bytes())
+}
+
+/// Writes 8-bit unsigned mono samples as a WAV file
+pub fn write_wav_u8<W: Write>(writer: &mut W, samples: &[u8], sample_rate: u32) -> Result<()> {
+    write_header(writer, samples.len() as u32, sample_rate, 8)?;
+    writer.write_all(samples)?;
+    // chunks are word aligned
+    if samples.len() % 2 == 1 {
+        writer.write_all(&[0])?;
+    }
+    Ok(())
+}
+
+/// Writes 16-bit signed mono samples as a WAV file
+pub fn write_wav_i16<W: Write>(writer: &mut W, samples: &[i16], sample_rate: u32) -> Result<()> {
+    write_header(writer, samples.len() as u32 * 2, sample_rate, 16)?;
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+    Ok(())
+}
+
+pub fn save_wav_u8<P: AsRef<Path>>(path: P, samples: &[u8], sample_rate: u32) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_wav_u8(&mut writer, samples, sample_rate)?;
+    writer.flush()
+}
+
+pub fn save_wav_i16<P: AsRef<Path>>(path: P, samples: &[i16], sample_rate: u32) -> Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    write_wav_i16(&mut writer, samples, sample_rate)?;
+    writer.flush()
+}
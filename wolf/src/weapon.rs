@@ -0,0 +1,283 @@
+//! The player's weapon at the bottom of the view and its attack animation,
+//! after T_Attack in WL_AGENT.C and DrawPlayerWeapon in WL_DRAW.C
+use crate::canvas::Canvas;
+use crate::player::{PlayerStatus, Weapon};
+use crate::resource::TexturePool;
+use crate::wolf_asset::Result;
+
+/// Wolfenstein 3D 1.4 (SPR_KNIFEREADY in WL_DEF.H), sprite of the knife at rest
+/// counted from the first sprite page. Every weapon has a ready frame followed by
+/// 4 attack frames
+pub const WL6_WEAPON_SPRITE: usize = 416;
+const WEAPON_FRAMES: usize = 5;
+
+/// What a step of the attack does, the `attack` column of attackinfo
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Step {
+    Nothing,
+    Shoot,
+    Stab,
+    /// the machine gun goes back two steps while the trigger is held
+    Repeat,
+    /// the chaingun does so and shoots at once
+    RepeatShoot,
+    Done,
+}
+
+/// (tics, step, weapon frame shown after it) per weapon
+const ATTACKS: [[(u32, Step, usize); 4]; 4] = [
+    [(6, Step::Nothing, 1), (6, Step::Stab, 2), (6, Step::Nothing, 3), (6, Step::Done, 4)],
+    [(6, Step::Nothing, 1), (6, Step::Shoot, 2), (6, Step::Nothing, 3), (6, Step::Done, 4)],
+    [(6, Step::Nothing, 1), (6, Step::Shoot, 2), (6, Step::Repeat, 3), (6, Step::Done, 4)],
+    [(6, Step::Nothing, 1), (6, Step::Shoot, 2), (6, Step::RepeatShoot, 3), (6, Step::Done, 4)],
+];
+
+/// Something the game has to resolve against the actors
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Attack {
+    Knife,
+    /// a bullet of `weapon`, the ammo is already taken
+    Shot(Weapon),
+}
+
+#[derive(Clone, Debug)]
+pub struct WeaponView {
+    /// the knife's ready frame, the other weapons follow it
+    first_sprite: usize,
+    /// step of the attack in progress, None while the weapon is at rest
+    attack: Option<usize>,
+    count: i32,
+    /// 0 is the ready frame, 1 to 4 the attack frames
+    frame: usize,
+}
+
+impl WeaponView {
+    pub fn new(first_sprite: usize) -> Self {
+        WeaponView {
+            first_sprite: first_sprite,
+            attack: None,
+            count: 0,
+            frame: 0,
+        }
+    }
+
+    pub fn frame(&self) -> usize {
+        self.frame
+    }
+
+    pub fn is_attacking(&self) -> bool {
+        self.attack.is_some()
+    }
+
+    /// Starts an attack unless one is running, as Cmd_Fire
+    pub fn fire(&mut self, status: &PlayerStatus) {
+        if self.attack.is_none() {
+            let (tics, _, frame) = ATTACKS[status.weapon.index()][0];
+            self.attack = Some(0);
+            self.count = tics as i32;
+            self.frame = frame;
+        }
+    }
+
+    /// Advances the attack by `tics`. Ammo is taken from `status` for every shot,
+    /// the knife is drawn once it runs out
+    pub fn update(
+        &mut self,
+        tics: u32,
+        trigger_held: bool,
+        status: &mut PlayerStatus,
+    ) -> Vec<Attack> {
+        let mut attacks = Vec::new();
+        let mut step = match self.attack {
+            Some(step) => step,
+            None => return attacks,
+        };
+        self.count -= tics.min(i32::MAX as u32) as i32;
+        while self.count <= 0 {
+            let table = &ATTACKS[status.weapon.index()];
+            let (step_tics, kind, _) = table[step];
+            match kind {
+                Step::Done => {
+                    if status.ammo == 0 {
+                        status.weapon = Weapon::Knife;
+                    }
+                    self.attack = None;
+                    self.frame = 0;
+                    return attacks;
+                }
+                // without ammo this clicks like any other shot
+                Step::RepeatShoot => {
+                    if status.ammo > 0 && trigger_held {
+                        step -= 2;
+                    }
+                    attacks.extend(shoot(status, &mut step));
+                }
+                Step::Shoot => attacks.extend(shoot(status, &mut step)),
+                Step::Repeat => {
+                    if status.ammo > 0 && trigger_held {
+                        step -= 2;
+                    }
+                }
+                Step::Stab => attacks.push(Attack::Knife),
+                Step::Nothing => {}
+            }
+            self.count += step_tics as i32;
+            step = (step + 1).min(table.len() - 1);
+            self.frame = table[step].2;
+            self.attack = Some(step);
+        }
+        attacks
+    }
+
+    /// Draws the weapon centred at the bottom of a view `view_height` rows high,
+    /// scaled to the view's height like the walls of a tile one unit away. Fails
+    /// when the weapon sprite cannot be loaded
+    pub fn draw(
+        &self,
+        weapon: Weapon,
+        textures: &mut TexturePool,
+        canvas: &mut Canvas,
+        view_height: usize,
+    ) -> Result<()> {
+        let (width, height) = canvas.size();
+        let view_height = view_height.min(height);
+        let sprite =
+            textures.get_sprite(self.first_sprite + weapon.index() * WEAPON_FRAMES + self.frame)?;
+        let size = view_height as f32;
+        let left = width as f32 / 2.0 - size / 2.0;
+        let first_col = left.max(0.0) as usize;
+        let last_col = ((left + size).ceil().max(0.0) as usize).min(width);
+        for col in first_col..last_col {
+            let u = (col as f32 + 0.5 - left) / size;
+            if !(0.0..1.0).contains(&u) {
+                continue;
+            }
+            for row in 0..view_height {
+                let v = (row as f32 + 0.5) / size;
+                if let Some(index) = sprite.sample_nearest(u, v) {
+                    canvas.set_pixel_by_color_index(col as u32, row as u32, index);
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A shot takes a bullet, without ammo the weapon clicks and the next step is
+/// skipped
+fn shoot(status: &mut PlayerStatus, step: &mut usize) -> Option<Attack> {
+    if status.ammo == 0 {
+        *step += 1;
+        None
+    } else {
+        status.ammo -= 1;
+        Some(Attack::Shot(status.weapon))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Attack, WeaponView, WL6_WEAPON_SPRITE};
+    use crate::canvas::Canvas;
+    use crate::player::{PlayerStatus, Weapon};
+    use crate::resource::TexturePool;
+    use crate::texture::Sprite;
+
+    #[test]
+    fn pistol_test() {
+        let mut status = PlayerStatus::default();
+        let mut view = WeaponView::new(WL6_WEAPON_SPRITE);
+        view.fire(&status);
+        assert_eq!(view.frame(), 1);
+        assert!(view.update(6, false, &mut status).is_empty());
+        assert_eq!(view.frame(), 2);
+        assert_eq!(view.update(6, false, &mut status), vec![Attack::Shot(Weapon::Pistol)]);
+        assert_eq!((view.frame(), status.ammo), (3, 7));
+        view.update(6, false, &mut status);
+        assert_eq!(view.frame(), 4);
+        view.update(6, false, &mut status);
+        assert_eq!(view.frame(), 0);
+        assert!(!view.is_attacking());
+    }
+
+    #[test]
+    fn automatic_test() {
+        // the chaingun fires on two frames while the trigger is held
+        let mut status = PlayerStatus {
+            weapon: Weapon::ChainGun,
+            ammo: 3,
+            ..Default::default()
+        };
+        let mut view = WeaponView::new(WL6_WEAPON_SPRITE);
+        view.fire(&status);
+        let shots = view.update(60, true, &mut status);
+        assert_eq!(shots.len(), 3);
+        assert_eq!(status.ammo, 0);
+
+        // out of ammo the attack ends and the knife comes out
+        view.update(60, true, &mut status);
+        assert!(!view.is_attacking());
+        assert_eq!(status.weapon, Weapon::Knife);
+        view.fire(&status);
+        let stabs = view.update(24, true, &mut status);
+        assert_eq!(stabs, vec![Attack::Knife]);
+    }
+
+    #[test]
+    fn chaingun_dry_test() {
+        let shot = vec![Attack::Shot(Weapon::ChainGun)];
+        let mut view = WeaponView::new(WL6_WEAPON_SPRITE);
+
+        // the last bullet goes on the first shot, the repeat step finds none
+        let mut status = PlayerStatus {
+            weapon: Weapon::ChainGun,
+            ammo: 1,
+            ..Default::default()
+        };
+        view.fire(&status);
+        assert!(view.update(6, true, &mut status).is_empty());
+        assert_eq!(view.update(6, true, &mut status), shot);
+        assert_eq!(view.frame(), 3);
+        assert!(view.update(6, true, &mut status).is_empty());
+        assert_eq!(view.frame(), 4);
+        view.update(6, true, &mut status);
+        assert!(!view.is_attacking());
+        assert_eq!(status.weapon, Weapon::Knife);
+
+        // the repeat takes the last bullet, the shot after it clicks
+        let mut status = PlayerStatus {
+            weapon: Weapon::ChainGun,
+            ammo: 2,
+            ..Default::default()
+        };
+        view.fire(&status);
+        assert!(view.update(6, true, &mut status).is_empty());
+        assert_eq!(view.update(6, true, &mut status), shot);
+        assert_eq!(view.update(6, true, &mut status), shot);
+        assert_eq!((view.frame(), status.ammo), (2, 0));
+        assert!(view.update(6, true, &mut status).is_empty());
+        assert_eq!(view.frame(), 4);
+        view.update(6, true, &mut status);
+        assert!(!view.is_attacking());
+    }
+
+    #[test]
+    fn draw_weapon_test() {
+        // every sprite is blank except the pistol's second attack frame
+        let mut sprites = (0..436).map(|_| Sprite::empty(64, 64)).collect::<Vec<Sprite>>();
+        sprites[423] = Sprite::new(vec![15; 64 * 64], vec![true; 64 * 64], 64, 64);
+        let mut textures = TexturePool::from_pages(Vec::new(), sprites);
+        let mut view = WeaponView::new(WL6_WEAPON_SPRITE);
+        let mut status = PlayerStatus::default();
+        view.fire(&status);
+        view.update(6, false, &mut status);
+        assert_eq!(view.frame(), 2);
+
+        let mut canvas = Canvas::new((200, 100));
+        view.draw(status.weapon, &mut textures, &mut canvas, 80).unwrap();
+        let white = |x: usize, y: usize| canvas.buffer()[(y * 200 + x) * 4] == 255;
+        // an 80 pixel square centred above the bottom 20 rows
+        assert!(white(60, 0) && white(139, 79));
+        assert!(!white(59, 40) && !white(140, 40) && !white(100, 80));
+    }
+}
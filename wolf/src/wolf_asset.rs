@@ -1,24 +1,248 @@
 use crate::io::{app_root_dir, asset_file};
-use std::io::prelude::*;
-use std::io::{Error, ErrorKind, Result};
+use crate::wav::save_wav_u8;
 
-use std::borrow::Borrow;
-use std::borrow::BorrowMut;
-use std::cell::RefCell;
-use std::collections::HashMap;
-use std::convert::AsRef;
+use std::fmt;
 use std::fs;
 use std::io::prelude::*;
 use std::io::SeekFrom;
-use std::path::Path;
-use std::rc::Rc;
+use std::path::{Path, PathBuf};
 
-const LEVELS_PER_EPS: usize = 10;
-const MAP_PLANE: usize = 3;
+pub const MAP_PLANE: usize = 3;
 const NEAR: u8 = 0xA7;
 const FAR: u8 = 0xA8;
 const ROOT: u8 = 254;
 
+/// Size in bytes of a level header in GAMEMAPS: 3 plane offsets, 3 plane
+/// lengths, width, height and a 16-byte name
+const LEVEL_HEADER_SIZE: usize = 38;
+/// Number of level slots in MAPHEAD
+const MAX_LEVELS: usize = 100;
+/// Signature at the start of GAMEMAPS
+const GAMEMAPS_SIGNATURE: &[u8; 8] = b"TED5v1.0";
+/// Signature following every level header in GAMEMAPS
+const LEVEL_SIGNATURE: &[u8; 4] = b"!ID!";
+
+#[derive(Debug)]
+pub enum WolfAssetError {
+    /// underlying read or seek failed at `offset`
+    Io { offset: u64, source: std::io::Error },
+    /// the file ends before a fixed size header starting at `offset` is complete
+    TruncatedHeader { offset: u64, expected: usize },
+    /// a compressed chunk starting at `offset` ends before it is fully decoded
+    TruncatedChunk { offset: u64 },
+    /// chunk `chunk` points at `offset`, which is outside of the file
+    BadChunkOffset { chunk: usize, offset: u64 },
+    /// MAPHEAD carries an RLEW flag which can not be used as a run marker
+    BadRlewFlag { offset: u64, flag: u16 },
+    /// decoding the chunk at `offset` writes past the end of the plane
+    DecompressionOverrun { offset: u64 },
+    /// a NEAR or FAR reference at `offset` points outside of the data expanded so far
+    BadBackReference { offset: u64 },
+    /// a Huffman code at `offset` reaches `node`, which is not in the dictionary
+    BadHuffmanNode { offset: u64, node: u16 },
+    /// requested level is not in the map atlas
+    LevelOutOfRange { index: usize, count: usize },
+    /// requested chunk is not in the VSWAP page table
+    ChunkOutOfRange { index: usize, count: usize },
+    /// a plane handed to the writer does not hold width * height tiles
+    PlaneSizeMismatch {
+        level: usize,
+        plane: usize,
+        expected: usize,
+        actual: usize,
+    },
+    /// a compressed plane does not fit the 16-bit length field of the level header
+    PlaneTooLarge {
+        level: usize,
+        plane: usize,
+        length: usize,
+    },
+    /// `length` bytes do not fit the 16-bit length header of a compressed chunk
+    ChunkTooLarge { length: usize },
+    /// no complete set of game files was found in `dir`
+    MissingGameData { dir: PathBuf },
+}
+
+impl WolfAssetError {
+    /// The byte offset in the file where parsing failed, if the error is tied to one
+    pub fn offset(&self) -> Option<u64> {
+        match *self {
+            WolfAssetError::Io { offset, .. }
+            | WolfAssetError::TruncatedHeader { offset, .. }
+            | WolfAssetError::TruncatedChunk { offset }
+            | WolfAssetError::BadChunkOffset { offset, .. }
+            | WolfAssetError::BadRlewFlag { offset, .. }
+            | WolfAssetError::DecompressionOverrun { offset }
+            | WolfAssetError::BadBackReference { offset }
+            | WolfAssetError::BadHuffmanNode { offset, .. } => Some(offset),
+            WolfAssetError::LevelOutOfRange { .. }
+            | WolfAssetError::ChunkOutOfRange { .. }
+            | WolfAssetError::PlaneSizeMismatch { .. }
+            | WolfAssetError::PlaneTooLarge { .. }
+            | WolfAssetError::ChunkTooLarge { .. }
+            | WolfAssetError::MissingGameData { .. } => None,
+        }
+    }
+}
+
+impl WolfAssetError {
+    /// Maps the offset carried by the error, used to turn buffer offsets into file offsets
+    pub(crate) fn relocate<F: Fn(u64) -> u64>(self, f: F) -> Self {
+        match self {
+            WolfAssetError::Io { offset, source } => WolfAssetError::Io {
+                offset: f(offset),
+                source,
+            },
+            WolfAssetError::TruncatedHeader { offset, expected } => {
+                WolfAssetError::TruncatedHeader {
+                    offset: f(offset),
+                    expected,
+                }
+            }
+            WolfAssetError::TruncatedChunk { offset } => {
+                WolfAssetError::TruncatedChunk { offset: f(offset) }
+            }
+            WolfAssetError::BadChunkOffset { chunk, offset } => WolfAssetError::BadChunkOffset {
+                chunk,
+                offset: f(offset),
+            },
+            WolfAssetError::BadRlewFlag { offset, flag } => WolfAssetError::BadRlewFlag {
+                offset: f(offset),
+                flag,
+            },
+            WolfAssetError::DecompressionOverrun { offset } => {
+                WolfAssetError::DecompressionOverrun { offset: f(offset) }
+            }
+            WolfAssetError::BadBackReference { offset } => {
+                WolfAssetError::BadBackReference { offset: f(offset) }
+            }
+            WolfAssetError::BadHuffmanNode { offset, node } => WolfAssetError::BadHuffmanNode {
+                offset: f(offset),
+                node,
+            },
+            other => other,
+        }
+    }
+}
+
+impl fmt::Display for WolfAssetError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WolfAssetError::Io { offset, source } => {
+                write!(f, "i/o error at offset {}: {}", offset, source)
+            }
+            WolfAssetError::TruncatedHeader { offset, expected } => write!(
+                f,
+                "truncated header at offset {}, expected {} bytes",
+                offset, expected
+            ),
+            WolfAssetError::TruncatedChunk { offset } => {
+                write!(f, "truncated chunk at offset {}", offset)
+            }
+            WolfAssetError::BadChunkOffset { chunk, offset } => {
+                write!(f, "chunk {} has bad offset {}", chunk, offset)
+            }
+            WolfAssetError::BadRlewFlag { offset, flag } => {
+                write!(f, "bad rlew flag {:#06x} at offset {}", flag, offset)
+            }
+            WolfAssetError::DecompressionOverrun { offset } => {
+                write!(f, "decompression overrun in chunk at offset {}", offset)
+            }
+            WolfAssetError::BadBackReference { offset } => {
+                write!(f, "bad back-reference at offset {}", offset)
+            }
+            WolfAssetError::BadHuffmanNode { offset, node } => {
+                write!(f, "bad huffman node {} at offset {}", node, offset)
+            }
+            WolfAssetError::LevelOutOfRange { index, count } => {
+                write!(f, "level index {} out of range ({} levels)", index, count)
+            }
+            WolfAssetError::ChunkOutOfRange { index, count } => {
+                write!(f, "chunk index {} out of range ({} chunks)", index, count)
+            }
+            WolfAssetError::PlaneSizeMismatch {
+                level,
+                plane,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "level {} plane {} has {} tiles, expected {}",
+                level, plane, actual, expected
+            ),
+            WolfAssetError::PlaneTooLarge {
+                level,
+                plane,
+                length,
+            } => write!(
+                f,
+                "level {} plane {} compresses to {} bytes",
+                level, plane, length
+            ),
+            WolfAssetError::ChunkTooLarge { length } => {
+                write!(f, "{} bytes do not fit a chunk length header", length)
+            }
+            WolfAssetError::MissingGameData { dir } => {
+                write!(f, "no game data found in {}", dir.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for WolfAssetError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            WolfAssetError::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+pub type Result<T> = std::result::Result<T, WolfAssetError>;
+
+/// Reads exactly `buf.len()` bytes at `offset`.
+/// Running out of data is reported as a truncated header
+pub(crate) fn read_header_at<R: Read + Seek>(
+    reader: &mut R,
+    offset: u64,
+    buf: &mut [u8],
+) -> Result<()> {
+    reader
+        .seek(SeekFrom::Start(offset))
+        .map_err(|e| WolfAssetError::Io { offset, source: e })?;
+    reader.read_exact(buf).map_err(|e| {
+        if e.kind() == std::io::ErrorKind::UnexpectedEof {
+            WolfAssetError::TruncatedHeader {
+                offset,
+                expected: buf.len(),
+            }
+        } else {
+            WolfAssetError::Io { offset, source: e }
+        }
+    })
+}
+
+fn stream_len<R: Seek>(reader: &mut R) -> Result<u64> {
+    reader
+        .seek(SeekFrom::End(0))
+        .map_err(|e| WolfAssetError::Io { offset: 0, source: e })
+}
+
+#[inline(always)]
+pub(crate) fn le_u16(buf: &[u8], at: usize) -> u16 {
+    u16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+#[inline(always)]
+pub(crate) fn le_i16(buf: &[u8], at: usize) -> i16 {
+    i16::from_le_bytes([buf[at], buf[at + 1]])
+}
+
+#[inline(always)]
+pub(crate) fn le_i32(buf: &[u8], at: usize) -> i32 {
+    i32::from_le_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]])
+}
+
 pub struct WolfMapAtlas {
     pub rlew_flag: u16,
     pub map_offset: Vec<i32>,
@@ -51,241 +275,703 @@ pub struct WolfVSWAP {
     pub chunk: Vec<(u32, i16)>,
 }
 
-pub fn read_vswap(vswap_file: &mut std::fs::File) -> WolfVSWAP {
-    vswap_file.seek(SeekFrom::Start(0)).unwrap();
-    let mut u16_buf = [0u8, 2];
-    vswap_file.read(&mut u16_buf).unwrap();
-    let num_of_chunk = i16::from_ne_bytes(u16_buf);
+pub fn read_vswap<R: Read + Seek>(vswap_file: &mut R) -> Result<WolfVSWAP> {
+    let file_len = stream_len(vswap_file)?;
 
-    vswap_file.read(&mut u16_buf).unwrap();
-    let sprite_start = i16::from_ne_bytes(u16_buf);
+    let mut head = [0u8; 6];
+    read_header_at(vswap_file, 0, &mut head)?;
+    let num_of_chunk = le_i16(&head, 0);
+    let sprite_start = le_i16(&head, 2);
+    let sound_start = le_i16(&head, 4);
+    let chunk_num = num_of_chunk.max(0) as usize;
 
-    vswap_file.read(&mut u16_buf).unwrap();
-    let sound_start = i16::from_ne_bytes(u16_buf);
-
-    let offset = (0..num_of_chunk)
-        .map(|_| {
-            let mut u32_buf = [0u8; 4];
-            vswap_file.read(&mut u32_buf).unwrap();
-            u32::from_ne_bytes(u32_buf)
-        })
-        .collect::<Vec<u32>>();
+    // page table: offsets as u32 followed by lengths as u16
+    let mut table = vec![0u8; chunk_num * 6];
+    read_header_at(vswap_file, head.len() as u64, &mut table)?;
+    let lengths = &table[chunk_num * 4..];
 
-    let length = (0..num_of_chunk)
-        .map(|_| {
-            let mut u16_buf = [0u8; 2];
-            vswap_file.read(&mut u16_buf).unwrap();
-            i16::from_ne_bytes(u16_buf)
+    let chunk = (0..chunk_num)
+        .map(|ind| {
+            let offset = u32::from_le_bytes([
+                table[ind * 4],
+                table[ind * 4 + 1],
+                table[ind * 4 + 2],
+                table[ind * 4 + 3],
+            ]);
+            let length = le_i16(lengths, ind * 2);
+            // sparse pages have offset 0 and are allowed
+            if offset != 0 && offset as u64 + length.max(0) as u64 > file_len {
+                return Err(WolfAssetError::BadChunkOffset {
+                    chunk: ind,
+                    offset: offset as u64,
+                });
+            }
+            Ok((offset, length))
         })
-        .collect::<Vec<i16>>();
+        .collect::<Result<Vec<(u32, i16)>>>()?;
 
-    WolfVSWAP {
+    Ok(WolfVSWAP {
         chunck_num: num_of_chunk,
         sprite_start: sprite_start,
         sound_start: sound_start,
-        chunk: offset
-            .iter()
-            .zip(length.iter())
-            .map(|(&x, &y)| (x, y))
-            .collect(),
-    }
+        chunk: chunk,
+    })
 }
 
 // returns a 64x64 Vec<u8>
-pub fn read_texture(
-    vswap_file: &mut std::fs::File,
+pub fn read_texture<R: Read + Seek>(
+    vswap_file: &mut R,
     vswap_header: &WolfVSWAP,
     texture_index: usize,
 ) -> Result<Vec<u8>> {
-    if texture_index >= vswap_header.sprite_start as usize {
-        Err(Error::new(ErrorKind::Other, format!("texture index out of range {}", texture_index)))
+    if texture_index >= vswap_header.sprite_start.max(0) as usize
+        || texture_index >= vswap_header.chunk.len()
+    {
+        Err(WolfAssetError::ChunkOutOfRange {
+            index: texture_index,
+            count: (vswap_header.sprite_start.max(0) as usize).min(vswap_header.chunk.len()),
+        })
     } else {
         let texture_offset = vswap_header.chunk[texture_index].0 as u64;
-        let texture_length = vswap_header.chunk[texture_index].1 as usize;
-        vswap_file.seek(SeekFrom::Start(texture_offset)).unwrap();
+        let texture_length = vswap_header.chunk[texture_index].1.max(0) as usize;
         let mut tex = vec![0u8; texture_length];
-        vswap_file.read_exact(&mut tex[0..]).unwrap();
+        read_header_at(vswap_file, texture_offset, &mut tex).map_err(|_| {
+            WolfAssetError::BadChunkOffset {
+                chunk: texture_index,
+                offset: texture_offset,
+            }
+        })?;
         Ok(tex)
     }
 }
 
+/// Side length of wall and sprite pages
+pub const PAGE_SIZE: usize = 64;
+
+/// Reads sprite `sprite_index`, counted from `sprite_start`, and decodes it with `decode_sprite`
+pub fn read_sprite<R: Read + Seek>(
+    vswap_file: &mut R,
+    vswap_header: &WolfVSWAP,
+    sprite_index: usize,
+) -> Result<(Vec<u8>, Vec<bool>)> {
+    let sprite_start = vswap_header.sprite_start.max(0) as usize;
+    let sprite_end = (vswap_header.sound_start.max(0) as usize).min(vswap_header.chunk.len());
+    let chunk_index = sprite_start + sprite_index;
+    if chunk_index >= sprite_end {
+        return Err(WolfAssetError::ChunkOutOfRange {
+            index: sprite_index,
+            count: sprite_end.saturating_sub(sprite_start),
+        });
+    }
+    let sprite_offset = vswap_header.chunk[chunk_index].0 as u64;
+    let sprite_length = vswap_header.chunk[chunk_index].1.max(0) as usize;
+    let mut chunk = vec![0u8; sprite_length];
+    read_header_at(vswap_file, sprite_offset, &mut chunk).map_err(|_| {
+        WolfAssetError::BadChunkOffset {
+            chunk: chunk_index,
+            offset: sprite_offset,
+        }
+    })?;
+    decode_sprite(&chunk).map_err(|e| e.relocate(|offset| sprite_offset + offset))
+}
+
+/// Decodes a VSWAP sprite page into 64x64 color indices and a mask of opaque pixels.
+///
+/// The page starts with the first and last non-empty column, followed by the offset of
+/// the post list of every column in between. A post is three words: end row * 2,
+/// the offset of row 0 of the post in the page, start row * 2. A zero end ends the list.
+///
+/// Unlike wall pages the output is laid out row by row, `y * 64 + x`, as `Texture2D` expects.
+/// Error offsets are relative to the start of `chunk`
+pub fn decode_sprite(chunk: &[u8]) -> Result<(Vec<u8>, Vec<bool>)> {
+    if chunk.len() < 4 {
+        return Err(WolfAssetError::TruncatedHeader {
+            offset: 0,
+            expected: 4,
+        });
+    }
+    let left = le_u16(chunk, 0) as usize;
+    let right = le_u16(chunk, 2) as usize;
+    if left > right || right >= PAGE_SIZE {
+        return Err(WolfAssetError::DecompressionOverrun { offset: 0 });
+    }
+    let table_end = 4 + (right - left + 1) * 2;
+    if chunk.len() < table_end {
+        return Err(WolfAssetError::TruncatedHeader {
+            offset: 4,
+            expected: table_end - 4,
+        });
+    }
+
+    let mut pixels = vec![0u8; PAGE_SIZE * PAGE_SIZE];
+    let mut mask = vec![false; PAGE_SIZE * PAGE_SIZE];
+    for x in left..=right {
+        let mut post = le_u16(chunk, 4 + (x - left) * 2) as usize;
+        loop {
+            if post + 2 > chunk.len() {
+                return Err(WolfAssetError::TruncatedChunk {
+                    offset: post as u64,
+                });
+            }
+            let end = le_u16(chunk, post) as usize / 2;
+            if end == 0 {
+                break;
+            }
+            if post + 6 > chunk.len() {
+                return Err(WolfAssetError::TruncatedChunk {
+                    offset: post as u64,
+                });
+            }
+            let source = le_i16(chunk, post + 2) as isize;
+            let start = le_u16(chunk, post + 4) as usize / 2;
+            if end > PAGE_SIZE || start > end {
+                return Err(WolfAssetError::DecompressionOverrun {
+                    offset: post as u64,
+                });
+            }
+            for y in start..end {
+                let at = source + y as isize;
+                if at < 0 || at as usize >= chunk.len() {
+                    return Err(WolfAssetError::TruncatedChunk {
+                        offset: post as u64,
+                    });
+                }
+                pixels[y * PAGE_SIZE + x] = chunk[at as usize];
+                mask[y * PAGE_SIZE + x] = true;
+            }
+            post += 6;
+        }
+    }
+    Ok((pixels, mask))
+}
+
+/// Sample rate of the digitized sounds in VSWAP, 8-bit unsigned mono
+pub const DIGI_SAMPLE_RATE: u32 = 7042;
+
+/// An entry of the sound info list stored in the last VSWAP chunk
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct WolfSoundInfo {
+    pub start_page: u16, // counted from sound_start
+    pub length: u16,     // in bytes, a sound can span several pages
+}
+
+/// Reads the sound info list from the last chunk of VSWAP.
+/// Every entry is a start page and a byte length, two words
+pub fn read_sound_info<R: Read + Seek>(
+    vswap_file: &mut R,
+    vswap_header: &WolfVSWAP,
+) -> Result<Vec<WolfSoundInfo>> {
+    let chunk_index = vswap_header.chunk.len().checked_sub(1).ok_or(
+        WolfAssetError::ChunkOutOfRange {
+            index: 0,
+            count: 0,
+        },
+    )?;
+    let (info_offset, info_length) = vswap_header.chunk[chunk_index];
+    let mut info = vec![0u8; info_length.max(0) as usize / 4 * 4];
+    read_header_at(vswap_file, info_offset as u64, &mut info)?;
+    Ok(info
+        .chunks_exact(4)
+        .map(|entry| WolfSoundInfo {
+            start_page: le_u16(entry, 0),
+            length: le_u16(entry, 2),
+        })
+        .collect())
+}
+
+/// Reassembles a digitized sound from its consecutive pages
+pub fn read_sound<R: Read + Seek>(
+    vswap_file: &mut R,
+    vswap_header: &WolfVSWAP,
+    sound: &WolfSoundInfo,
+) -> Result<Vec<u8>> {
+    // the last chunk is the info list and never holds samples
+    let sound_end = vswap_header.chunk.len().saturating_sub(1);
+    let mut page = vswap_header.sound_start.max(0) as usize + sound.start_page as usize;
+    if page >= sound_end {
+        return Err(WolfAssetError::ChunkOutOfRange {
+            index: page,
+            count: sound_end,
+        });
+    }
+
+    let mut samples = Vec::with_capacity(sound.length as usize);
+    while samples.len() < sound.length as usize {
+        if page >= sound_end {
+            // the sound claims more bytes than the pages hold
+            let last = vswap_header.chunk[sound_end - 1];
+            return Err(WolfAssetError::TruncatedChunk {
+                offset: last.0 as u64 + last.1.max(0) as u64,
+            });
+        }
+        let (page_offset, page_length) = vswap_header.chunk[page];
+        let take = (page_length.max(0) as usize).min(sound.length as usize - samples.len());
+        let start = samples.len();
+        samples.resize(start + take, 0);
+        read_header_at(vswap_file, page_offset as u64, &mut samples[start..]).map_err(|_| {
+            WolfAssetError::BadChunkOffset {
+                chunk: page,
+                offset: page_offset as u64,
+            }
+        })?;
+        page += 1;
+    }
+    Ok(samples)
+}
+
+/// Writes every digitized sound to `dir` as `digi_NNN.wav`, returns the number of files written.
+/// Unused entries of the info list are skipped
+pub fn export_sounds<R: Read + Seek, P: AsRef<Path>>(
+    vswap_file: &mut R,
+    vswap_header: &WolfVSWAP,
+    dir: P,
+) -> Result<usize> {
+    let mut written = 0;
+    for (index, sound) in read_sound_info(vswap_file, vswap_header)?
+        .iter()
+        .enumerate()
+    {
+        if sound.start_page == 0xFFFF || sound.length == 0 {
+            continue;
+        }
+        let samples = read_sound(vswap_file, vswap_header, sound)?;
+        let path = dir.as_ref().join(format!("digi_{:03}.wav", index));
+        save_wav_u8(path, &samples, DIGI_SAMPLE_RATE)
+            .map_err(|e| WolfAssetError::Io { offset: 0, source: e })?;
+        written += 1;
+    }
+    Ok(written)
+}
+
 ///
 /// MAPHEAD.WL6
-pub fn read_atlas<T: AsRef<Path>>(path: T) -> WolfMapAtlas {
-    let atlas = fs::read(path).unwrap();
+pub fn read_atlas<T: AsRef<Path>>(path: T) -> Result<WolfMapAtlas> {
+    let atlas = fs::read(path).map_err(|e| WolfAssetError::Io { offset: 0, source: e })?;
+    parse_atlas(&atlas)
+}
 
+/// Parses the content of a MAPHEAD file
+pub fn parse_atlas(atlas: &[u8]) -> Result<WolfMapAtlas> {
     // MAPEHAD format
     // the first word size is the rlew_flag
     // the rest of it is an array with length of 100 with i32 element
     // 402 bytes in total
-    let rlew_flag = u16::from_ne_bytes([atlas[0], atlas[1]]);
-    let map_atlas: Vec<i32> = (2..atlas.len())
+    if atlas.len() < 2 {
+        return Err(WolfAssetError::TruncatedHeader {
+            offset: 0,
+            expected: 2,
+        });
+    }
+    let rlew_flag = le_u16(atlas, 0);
+    // with a zero flag every empty tile would be read as the start of a run
+    if rlew_flag == 0 {
+        return Err(WolfAssetError::BadRlewFlag {
+            offset: 0,
+            flag: rlew_flag,
+        });
+    }
+    // some versions append tile info after the offsets, ignore the trailing bytes
+    let map_atlas: Vec<i32> = (2..atlas.len().saturating_sub(3))
         .step_by(4)
-        .map(|ind| {
-            i32::from_ne_bytes([
-                atlas[ind + 0],
-                atlas[ind + 1],
-                atlas[ind + 3],
-                atlas[ind + 3],
-            ])
-        })
+        .map(|ind| le_i32(atlas, ind))
         .collect();
 
-    WolfMapAtlas {
+    Ok(WolfMapAtlas {
         rlew_flag: rlew_flag,
         map_offset: map_atlas,
-    }
+    })
 }
-pub fn read_level(
+
+/// Reads the header of `level` of `episode`, both counting from 1. The levels of all
+/// episodes follow each other in MAPHEAD, `levels_per_episode` of them per episode
+pub fn read_level<R: Read + Seek>(
     map_atlas: &WolfMapAtlas,
-    map_head: &mut fs::File,
+    map_head: &mut R,
+    levels_per_episode: usize,
     episode: i32,
     level: i32,
-) -> WolfLevel {
-    let map_index = (episode - 1) as usize * LEVELS_PER_EPS + level as usize - 1;
-
-    let map_meta_offset = map_atlas.map_offset[map_index] as u64;
-
-    map_head.seek(SeekFrom::Start(map_meta_offset)).unwrap();
+) -> Result<WolfLevel> {
+    let count = map_atlas.map_offset.len();
+    if episode < 1 || level < 1 || level as usize > levels_per_episode {
+        return Err(WolfAssetError::LevelOutOfRange { index: 0, count });
+    }
+    let map_index = (episode - 1) as usize * levels_per_episode + level as usize - 1;
+    if map_index >= count {
+        return Err(WolfAssetError::LevelOutOfRange {
+            index: map_index,
+            count,
+        });
+    }
 
-    let mut map_offset = [0; 3];
-    for ind in 0..MAP_PLANE {
-        let mut i32_buf = [0u8; 4];
-        map_head.read(&mut i32_buf).unwrap();
-        map_offset[ind] = i32::from_ne_bytes(i32_buf);
+    // unused slots in the atlas are zero
+    let map_meta_offset = map_atlas.map_offset[map_index];
+    if map_meta_offset <= 0 {
+        return Err(WolfAssetError::BadChunkOffset {
+            chunk: map_index,
+            offset: map_meta_offset as u64,
+        });
     }
 
-    let mut cc_length = [0i16; 3];
+    let mut buf = [0u8; LEVEL_HEADER_SIZE];
+    read_header_at(map_head, map_meta_offset as u64, &mut buf)?;
+
+    let mut map_offset = [0; MAP_PLANE];
+    let mut cc_length = [0i16; MAP_PLANE];
     for ind in 0..MAP_PLANE {
-        let mut u16_buf = [0u8; 2];
-        map_head.read(&mut u16_buf).unwrap();
-        cc_length[ind] = i16::from_ne_bytes(u16_buf);
+        map_offset[ind] = le_i32(&buf, ind * 4);
+        cc_length[ind] = le_i16(&buf, 12 + ind * 2);
     }
-    let mut u16_buf = [0u8; 2];
-    map_head.read(&mut u16_buf).unwrap();
-    let width = i16::from_ne_bytes(u16_buf);
-
-    map_head.read(&mut u16_buf).unwrap();
-    let height = i16::from_ne_bytes(u16_buf);
+    let width = le_i16(&buf, 18);
+    let height = le_i16(&buf, 20);
 
     let mut name_buf = [0u8; 16];
-    map_head.read(&mut name_buf).unwrap();
+    name_buf.copy_from_slice(&buf[22..38]);
 
-    WolfLevel {
+    Ok(WolfLevel {
         map_offset: map_offset,
         cc_length: cc_length,
         width: width,
         height: height,
         name: name_buf,
-    }
+    })
 }
 
-pub fn read_map(
+pub fn read_map<R: Read + Seek>(
     atlas: &WolfMapAtlas,
     level_head: &WolfLevel,
-    map_file: &mut fs::File,
+    map_file: &mut R,
     map: usize,
-) -> Vec<u16> {
-    let cc_length = level_head.cc_length[map] as usize;
-
-    // read by word
-    map_file
-        .seek(SeekFrom::Start(level_head.map_offset[map] as u64))
-        .unwrap();
-
-    let carmark_buffer: Vec<u16> = (0..cc_length / 2)
-        .map(|_| {
-            let mut word_buf = [0u8; 2];
-            map_file.read(&mut word_buf).unwrap();
-            u16::from_ne_bytes(word_buf)
-        })
-        .collect();
+) -> Result<Vec<u16>> {
+    if map >= MAP_PLANE {
+        return Err(WolfAssetError::ChunkOutOfRange {
+            index: map,
+            count: MAP_PLANE,
+        });
+    }
+    let chunk_offset = level_head.map_offset[map] as u64;
+    let cc_length = level_head.cc_length[map].max(0) as usize;
+    let file_len = stream_len(map_file)?;
+    if level_head.map_offset[map] <= 0 || chunk_offset + cc_length as u64 > file_len {
+        return Err(WolfAssetError::BadChunkOffset {
+            chunk: map,
+            offset: chunk_offset,
+        });
+    }
 
-    let rlew_length_words = carmark_buffer[0] / 2;
-    let mut rlew_buffer: Vec<u16> = vec![0u16; rlew_length_words as usize];
-
-    let map_unit_size = (level_head.width * level_head.height) as usize;
-    // let map_bytes = (map_unit_size) as usize * std::mem::size_of::<u16>();
-    let mut map = vec![0u16; map_unit_size];
-
-    // 受不了了，直接搞unsafe
-    unsafe {
-        // carmarck expand: expand carmarck_buffer to rlew_buffer
-        let mut read: *const u8 = carmark_buffer.as_ptr().add(1) as *const u8;
-        let dest = rlew_buffer.as_mut_ptr() as *mut u16;
-        let mut write: *mut u8 = dest as *mut u8;
-        let mut copy: *const u8;
-        let mut offset: u16;
-
-        let mut length: i32 = rlew_length_words as i32;
-        while length > 0 {
-            let count = *(read);
-            read = read.add(1);
-            let flag = *(read);
-            read = read.add(1);
-            if flag == NEAR && count != 0 {
-                offset = (*read) as u16;
-                read = read.add(1);
-                copy = write.sub(2 * offset as usize);
-                for _ in 0..count {
-                    *write = *copy;
-                    write = write.add(1);
-                    copy = copy.add(1);
-                    *write = *copy;
-                    write = write.add(1);
-                    copy = copy.add(1);
-                    length -= 1;
+    let mut carmack_buffer = vec![0u8; cc_length];
+    read_header_at(map_file, chunk_offset, &mut carmack_buffer)
+        .map_err(|_| WolfAssetError::TruncatedChunk { offset: chunk_offset })?;
+
+    let rlew_buffer =
+        carmack_expand(&carmack_buffer).map_err(|e| e.relocate(|offset| chunk_offset + offset))?;
+    // rlew offsets point into the expanded data, the best we can report is the chunk
+    let map_data =
+        rlew_expand(&rlew_buffer, atlas.rlew_flag).map_err(|e| e.relocate(|_| chunk_offset))?;
+
+    let map_unit_size = (level_head.width.max(0) as usize) * (level_head.height.max(0) as usize);
+    if map_data.len() < map_unit_size {
+        return Err(WolfAssetError::TruncatedChunk { offset: chunk_offset });
+    }
+    if map_data.len() > map_unit_size {
+        return Err(WolfAssetError::DecompressionOverrun { offset: chunk_offset });
+    }
+    Ok(map_data)
+}
+
+/// Expands a Carmack compressed chunk.
+///
+/// The chunk starts with the expanded length in bytes, followed by a stream of words.
+/// A word whose high byte is NEAR or FAR is a back-reference: the low byte is the
+/// number of words to copy, NEAR is followed by a byte giving the distance back from
+/// the write position, FAR by a word giving the absolute position in the output.
+/// A count of 0 escapes a literal word with that high byte, its low byte follows.
+///
+/// Error offsets are relative to the start of `source`
+pub fn carmack_expand(source: &[u8]) -> Result<Vec<u16>> {
+    if source.len() < 2 {
+        return Err(WolfAssetError::TruncatedChunk { offset: 0 });
+    }
+    let length = le_u16(source, 0) as usize / 2;
+    let mut dest = Vec::with_capacity(length);
+
+    let truncated = |at: usize| WolfAssetError::TruncatedChunk { offset: at as u64 };
+    let mut read = 2usize;
+    while dest.len() < length {
+        if read + 2 > source.len() {
+            return Err(truncated(read));
+        }
+        let word_at = read;
+        let count = source[read] as usize;
+        let flag = source[read + 1];
+        read += 2;
+        if (flag == NEAR || flag == FAR) && count != 0 {
+            let copy = if flag == NEAR {
+                let back = *source.get(read).ok_or_else(|| truncated(read))? as usize;
+                read += 1;
+                dest.len().checked_sub(back)
+            } else {
+                if read + 2 > source.len() {
+                    return Err(truncated(read));
+                }
+                let offset = le_u16(source, read) as usize;
+                read += 2;
+                Some(offset)
+            };
+            let copy = match copy {
+                Some(copy) if copy < dest.len() => copy,
+                _ => {
+                    return Err(WolfAssetError::BadBackReference {
+                        offset: word_at as u64,
+                    })
                 }
-            } else if flag == FAR && count != 0 {
-                offset = *(read as *const u16);
-                read = read.add(2);
-                copy = dest.offset(offset as isize) as *mut u8;
-                for _ in 0..count {
-                    *write = *copy;
-                    write = write.add(1);
-                    copy = copy.add(1);
-                    *write = *copy;
-                    write = write.add(1);
-                    copy = copy.add(1);
-                    length -= 1;
+            };
+            if dest.len() + count > length {
+                return Err(WolfAssetError::DecompressionOverrun {
+                    offset: word_at as u64,
+                });
+            }
+            // the source may overlap the words being written, copy one by one
+            for ind in 0..count {
+                let word = dest[copy + ind];
+                dest.push(word);
+            }
+        } else if flag == NEAR || flag == FAR {
+            // escaped literal, the low byte follows the flag
+            let low = *source.get(read).ok_or_else(|| truncated(read))?;
+            read += 1;
+            dest.push(u16::from_le_bytes([low, flag]));
+        } else {
+            dest.push(u16::from_le_bytes([count as u8, flag]));
+        }
+    }
+    Ok(dest)
+}
+
+/// Compresses `data` into a Carmack chunk readable by `carmack_expand`.
+///
+/// Fails when `data` holds more bytes than its u16 length header can count
+pub fn carmack_compress(data: &[u16]) -> Result<Vec<u8>> {
+    const MAX_COUNT: usize = 255;
+    // FAR references address words with a u16
+    let far_limit = data.len().min(u16::MAX as usize + 1);
+
+    let mut dest = Vec::with_capacity(data.len() * 2 + 2);
+    dest.extend_from_slice(&length_header(data)?.to_le_bytes());
+
+    let mut pos = 0usize;
+    while pos < data.len() {
+        // longest earlier match, the first one found wins so NEAR is preferred
+        let (mut best_len, mut best_at) = (0usize, 0usize);
+        let max_len = MAX_COUNT.min(data.len() - pos);
+        for start in (0..pos.min(far_limit)).rev() {
+            let mut len = 0;
+            while len < max_len && data[start + len] == data[pos + len] {
+                len += 1;
+            }
+            if len > best_len {
+                best_len = len;
+                best_at = start;
+                if len == max_len {
+                    break;
                 }
-            } else if (flag == NEAR || flag == FAR) && count == 0 {
-                *write = *read;
-                write = write.add(1);
-                read = read.add(1);
-                *write = flag;
-                write = write.add(1);
-                length -= 1;
+            }
+        }
+
+        let back = pos - best_at;
+        if best_len >= 2 && back <= MAX_COUNT {
+            dest.extend_from_slice(&[best_len as u8, NEAR, back as u8]);
+            pos += best_len;
+        } else if best_len >= 3 {
+            dest.extend_from_slice(&[best_len as u8, FAR]);
+            dest.extend_from_slice(&(best_at as u16).to_le_bytes());
+            pos += best_len;
+        } else {
+            let [low, high] = data[pos].to_le_bytes();
+            if high == NEAR || high == FAR {
+                dest.extend_from_slice(&[0, high, low]);
             } else {
-                *(write) = count;
-                write = write.add(1);
-                *(write) = flag;
-                write = write.add(1);
-                length -= 1;
+                dest.extend_from_slice(&[low, high]);
             }
+            pos += 1;
         }
     }
+    Ok(dest)
+}
 
-    // rlew_expand expand rlew_buffer to final map buffer
-    let (mut read, mut write) = (1, 0);
-    while write < map_unit_size {
-        let current_word = rlew_buffer[read];
-        read += 1;
-        if current_word == atlas.rlew_flag {
-            let count = rlew_buffer[read];
-            read += 1;
-            for _ in 0..count {
-                map[write] = rlew_buffer[read];
-                write += 1;
+/// The expanded length in bytes that starts a compressed chunk
+fn length_header(data: &[u16]) -> Result<u16> {
+    let length = data.len() * 2;
+    if length > u16::MAX as usize {
+        return Err(WolfAssetError::ChunkTooLarge { length: length });
+    }
+    Ok(length as u16)
+}
+
+/// Expands RLEW compressed words.
+///
+/// The first word is the expanded length in bytes. `rlew_flag` followed by a count and
+/// a value is a run, any other word is copied as is.
+///
+/// Error offsets are byte offsets into `source`
+pub fn rlew_expand(source: &[u16], rlew_flag: u16) -> Result<Vec<u16>> {
+    let length = *source
+        .first()
+        .ok_or(WolfAssetError::TruncatedChunk { offset: 0 })? as usize
+        / 2;
+    let mut dest = Vec::with_capacity(length);
+
+    let mut read = 1usize;
+    while dest.len() < length {
+        let current_word = *source.get(read).ok_or(WolfAssetError::TruncatedChunk {
+            offset: read as u64 * 2,
+        })?;
+        if current_word == rlew_flag {
+            if read + 3 > source.len() {
+                return Err(WolfAssetError::TruncatedChunk {
+                    offset: read as u64 * 2,
+                });
             }
+            let count = source[read + 1] as usize;
+            let value = source[read + 2];
+            if dest.len() + count > length {
+                return Err(WolfAssetError::DecompressionOverrun {
+                    offset: read as u64 * 2,
+                });
+            }
+            dest.resize(dest.len() + count, value);
+            read += 3;
+        } else {
+            dest.push(current_word);
             read += 1;
+        }
+    }
+    Ok(dest)
+}
+
+/// Compresses `data` into RLEW words readable by `rlew_expand`.
+///
+/// Fails when `data` holds more bytes than its u16 length header can count
+pub fn rlew_compress(data: &[u16], rlew_flag: u16) -> Result<Vec<u16>> {
+    let mut dest = Vec::with_capacity(data.len() + 1);
+    dest.push(length_header(data)?);
+
+    let mut pos = 0usize;
+    while pos < data.len() {
+        let value = data[pos];
+        let mut count = 1;
+        while pos + count < data.len() && data[pos + count] == value && count < u16::MAX as usize
+        {
+            count += 1;
+        }
+        // a run costs three words, the flag itself can only be written as a run
+        if count > 3 || value == rlew_flag {
+            dest.extend_from_slice(&[rlew_flag, count as u16, value]);
         } else {
-            map[write] = current_word;
-            write += 1;
+            dest.extend((0..count).map(|_| value));
         }
+        pos += count;
+    }
+    Ok(dest)
+}
+
+/// Writes a MAPHEAD + GAMEMAPS pair.
+///
+/// `levels[i]` goes to slot `i` of MAPHEAD, i.e. episode `i / 10 + 1`, level `i % 10 + 1`
+/// with the 10 levels per episode of Wolfenstein 3D.
+/// The plane offsets and lengths of the headers are ignored and computed while writing,
+/// every plane is RLEW then Carmack compressed. Returns the atlas that was written
+pub fn write_maps<M: Write, G: Write>(
+    rlew_flag: u16,
+    levels: &[(WolfLevel, [Vec<u16>; MAP_PLANE])],
+    map_head: &mut M,
+    game_maps: &mut G,
+) -> Result<WolfMapAtlas> {
+    if levels.len() > MAX_LEVELS {
+        return Err(WolfAssetError::LevelOutOfRange {
+            index: levels.len() - 1,
+            count: MAX_LEVELS,
+        });
+    }
+    if rlew_flag == 0 {
+        return Err(WolfAssetError::BadRlewFlag {
+            offset: 0,
+            flag: rlew_flag,
+        });
     }
 
-    map
+    let mut position = 0u64;
+    let mut write = |game_maps: &mut G, bytes: &[u8]| -> Result<()> {
+        game_maps
+            .write_all(bytes)
+            .map_err(|e| WolfAssetError::Io {
+                offset: position,
+                source: e,
+            })?;
+        position += bytes.len() as u64;
+        Ok(())
+    };
+
+    write(game_maps, GAMEMAPS_SIGNATURE)?;
+    let mut offset = GAMEMAPS_SIGNATURE.len() as u64;
+
+    let mut map_offset = vec![0i32; MAX_LEVELS];
+    for (level_index, (level, planes)) in levels.iter().enumerate() {
+        let tiles = (level.width.max(0) as usize) * (level.height.max(0) as usize);
+
+        let mut header = [0u8; LEVEL_HEADER_SIZE];
+        for (plane_index, plane) in planes.iter().enumerate() {
+            if plane.len() != tiles {
+                return Err(WolfAssetError::PlaneSizeMismatch {
+                    level: level_index,
+                    plane: plane_index,
+                    expected: tiles,
+                    actual: plane.len(),
+                });
+            }
+            let too_large = |length| WolfAssetError::PlaneTooLarge {
+                level: level_index,
+                plane: plane_index,
+                length: length,
+            };
+            let chunk = rlew_compress(plane, rlew_flag)
+                .and_then(|words| carmack_compress(&words))
+                .map_err(|e| match e {
+                    WolfAssetError::ChunkTooLarge { length } => too_large(length),
+                    other => other,
+                })?;
+            if chunk.len() > i16::MAX as usize {
+                return Err(too_large(chunk.len()));
+            }
+            header[plane_index * 4..plane_index * 4 + 4]
+                .copy_from_slice(&(offset as i32).to_le_bytes());
+            header[12 + plane_index * 2..14 + plane_index * 2]
+                .copy_from_slice(&(chunk.len() as i16).to_le_bytes());
+            write(game_maps, &chunk)?;
+            offset += chunk.len() as u64;
+        }
+        header[18..20].copy_from_slice(&level.width.to_le_bytes());
+        header[20..22].copy_from_slice(&level.height.to_le_bytes());
+        header[22..38].copy_from_slice(&level.name);
+
+        map_offset[level_index] = offset as i32;
+        write(game_maps, &header)?;
+        write(game_maps, LEVEL_SIGNATURE)?;
+        offset += (LEVEL_HEADER_SIZE + LEVEL_SIGNATURE.len()) as u64;
+    }
+
+    let mut atlas = Vec::with_capacity(2 + MAX_LEVELS * 4);
+    atlas.extend_from_slice(&rlew_flag.to_le_bytes());
+    map_offset
+        .iter()
+        .for_each(|offset| atlas.extend_from_slice(&offset.to_le_bytes()));
+    map_head
+        .write_all(&atlas)
+        .map_err(|e| WolfAssetError::Io { offset: 0, source: e })?;
+
+    Ok(WolfMapAtlas {
+        rlew_flag: rlew_flag,
+        map_offset: map_offset,
+    })
 }
 
 fn read_vswap_header(vswap_file: &mut std::fs::File) {
@@ -294,26 +980,411 @@ fn read_vswap_header(vswap_file: &mut std::fs::File) {
 
 #[cfg(test)]
 mod tests {
-    use super::{read_atlas, read_level, read_map, read_texture, read_vswap};
-    use crate::io::{app_root_dir, asset_file};
+    use super::{
+        carmack_compress, carmack_expand, decode_sprite, read_sound, read_sound_info, parse_atlas, read_atlas, read_level, read_map,
+        read_texture, read_vswap, rlew_compress, rlew_expand, write_maps, WolfAssetError,
+        WolfLevel, WolfMapAtlas, WolfSoundInfo, FAR, NEAR,
+    };
+    use crate::wav::write_wav_u8;
+    use crate::gamedata::GameData;
+    use crate::io::app_root_dir;
+    use std::fs::File;
+    use std::io::Cursor;
 
     #[test]
     fn read_map_test() {
-        let mut atlas_dir = app_root_dir().unwrap();
-        atlas_dir.push("resources/orignal/MAPHEAD.WL6");
-        let atlas = read_atlas(atlas_dir);
+        let data = GameData::discover(app_root_dir().unwrap().join("resources/original")).unwrap();
+        let atlas = read_atlas(&data.files.map_head).unwrap();
 
-        let mut map_head = asset_file("resources/orignal/GAMEMAPS.WL6").unwrap();
+        let mut map_head = File::open(&data.files.game_maps).unwrap();
 
-        let level_data = read_level(&atlas, &mut map_head, 1, 1);
-        let map_data = read_map(&atlas, &level_data, &mut map_head, 0);
+        let level_data = read_level(&atlas, &mut map_head, 10, 1, 1).unwrap();
+        let map_data = read_map(&atlas, &level_data, &mut map_head, 0).unwrap();
     }
     #[test]
     fn read_vswap_test() {
-        let mut vswap_file = asset_file("resources/original/VSWAP.WL6").unwrap();
-        let vswap_header = read_vswap(&mut vswap_file);
+        let data = GameData::discover(app_root_dir().unwrap().join("resources/original")).unwrap();
+        let mut vswap_file = File::open(&data.files.vswap).unwrap();
+        let vswap_header = read_vswap(&mut vswap_file).unwrap();
         let tex_buf = read_texture(&mut vswap_file, &vswap_header, 1).unwrap();
         //println!("{} {} {} ",vswap_header.chunck_num, vswap_header.sprite_start,vswap_header.sound_start);
         println!("{:?}", tex_buf);
     }
+
+    #[test]
+    fn truncated_vswap_test() {
+        // header claims 4 chunks but the page table is missing
+        let mut file = Cursor::new(vec![4u8, 0, 2, 0, 3, 0, 0, 0]);
+        match read_vswap(&mut file) {
+            Err(WolfAssetError::TruncatedHeader { offset, expected }) => {
+                assert_eq!(offset, 6);
+                assert_eq!(expected, 24);
+            }
+            _ => panic!("expected truncated header"),
+        }
+    }
+
+    #[test]
+    fn bad_chunk_offset_test() {
+        let mut data = vec![1u8, 0, 1, 0, 1, 0];
+        data.extend_from_slice(&1000u32.to_le_bytes());
+        data.extend_from_slice(&64i16.to_le_bytes());
+        match read_vswap(&mut Cursor::new(data)) {
+            Err(WolfAssetError::BadChunkOffset { chunk, offset }) => {
+                assert_eq!(chunk, 0);
+                assert_eq!(offset, 1000);
+            }
+            _ => panic!("expected bad chunk offset"),
+        }
+    }
+
+    #[test]
+    fn bad_atlas_test() {
+        assert!(matches!(
+            parse_atlas(&[0xCD]),
+            Err(WolfAssetError::TruncatedHeader { offset: 0, .. })
+        ));
+        assert!(matches!(
+            parse_atlas(&[0, 0, 0, 0, 0, 0]),
+            Err(WolfAssetError::BadRlewFlag { flag: 0, .. })
+        ));
+        let atlas = parse_atlas(&[0xCD, 0xAB, 0x78, 0x56, 0x34, 0x12]).unwrap();
+        assert_eq!(atlas.rlew_flag, 0xABCD);
+        assert_eq!(atlas.map_offset, vec![0x12345678]);
+    }
+
+    #[test]
+    fn level_out_of_range_test() {
+        let atlas = WolfMapAtlas {
+            rlew_flag: 0xABCD,
+            map_offset: vec![8, 0],
+        };
+        let mut file = Cursor::new(vec![0u8; 8]);
+        assert!(matches!(
+            read_level(&atlas, &mut file, 10, 1, 3),
+            Err(WolfAssetError::LevelOutOfRange { index: 2, count: 2 })
+        ));
+        assert!(matches!(
+            read_level(&atlas, &mut file, 10, 1, 2),
+            Err(WolfAssetError::BadChunkOffset { chunk: 1, .. })
+        ));
+        let err = read_level(&atlas, &mut file, 10, 1, 1).err().unwrap();
+        assert_eq!(err.offset(), Some(8));
+
+        // with a single level per episode the second slot is episode 2
+        assert!(matches!(
+            read_level(&atlas, &mut file, 1, 1, 2),
+            Err(WolfAssetError::LevelOutOfRange { index: 0, count: 2 })
+        ));
+        assert!(matches!(
+            read_level(&atlas, &mut file, 1, 2, 1),
+            Err(WolfAssetError::BadChunkOffset { chunk: 1, .. })
+        ));
+    }
+
+    /// A 64x64 plane with long runs, repeated rows, noise and words that collide
+    /// with the NEAR/FAR tags and the rlew flag
+    fn synthetic_plane() -> Vec<u16> {
+        let mut seed = 0x1234_5678u32;
+        let mut plane = vec![0u16; 64 * 64];
+        for y in 0..64 {
+            for x in 0..64 {
+                plane[y * 64 + x] = if x == 0 || y == 0 || x == 63 || y == 63 {
+                    1
+                } else if y % 8 == 0 {
+                    (x % 5) as u16 + 90
+                } else if y > 40 {
+                    seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+                    match (seed >> 16) % 6 {
+                        0 => u16::from_le_bytes([(seed >> 8) as u8, NEAR]),
+                        1 => u16::from_le_bytes([0, FAR]),
+                        2 => 0xABCD,
+                        _ => (seed >> 16) as u16,
+                    }
+                } else {
+                    108
+                };
+            }
+        }
+        plane
+    }
+
+    #[test]
+    fn carmack_round_trip_test() {
+        let plane = synthetic_plane();
+        let compressed = carmack_compress(&plane).unwrap();
+        assert!(compressed.len() < plane.len() * 2);
+        assert_eq!(carmack_expand(&compressed).unwrap(), plane);
+
+        // long planes need FAR references past the NEAR window
+        let long: Vec<u16> = (0..2000u16).chain(0..2000u16).collect();
+        assert_eq!(carmack_expand(&carmack_compress(&long).unwrap()).unwrap(), long);
+
+        assert_eq!(carmack_expand(&carmack_compress(&[]).unwrap()).unwrap(), Vec::<u16>::new());
+    }
+
+    #[test]
+    fn rlew_round_trip_test() {
+        let plane = synthetic_plane();
+        let compressed = rlew_compress(&plane, 0xABCD).unwrap();
+        assert!(compressed.len() < plane.len());
+        assert_eq!(rlew_expand(&compressed, 0xABCD).unwrap(), plane);
+
+        // both stages as stored in GAMEMAPS
+        let chunk = carmack_compress(&rlew_compress(&plane, 0xABCD).unwrap()).unwrap();
+        let expanded = rlew_expand(&carmack_expand(&chunk).unwrap(), 0xABCD).unwrap();
+        assert_eq!(expanded, plane);
+
+        // the length header counts bytes in a u16
+        let huge = vec![0u16; 32768];
+        assert!(matches!(
+            rlew_compress(&huge, 0xABCD),
+            Err(WolfAssetError::ChunkTooLarge { length: 65536 })
+        ));
+        assert!(matches!(
+            carmack_compress(&huge),
+            Err(WolfAssetError::ChunkTooLarge { length: 65536 })
+        ));
+        assert!(rlew_compress(&huge[1..], 0xABCD).is_ok());
+    }
+
+    #[test]
+    fn carmack_bad_reference_test() {
+        // NEAR reference before anything was written
+        let near = [4u8, 0, 1, NEAR, 1];
+        assert!(matches!(
+            carmack_expand(&near),
+            Err(WolfAssetError::BadBackReference { offset: 2 })
+        ));
+        // FAR reference to a word not yet expanded
+        let far = [4u8, 0, 0x11, 0x22, 1, FAR, 5, 0];
+        assert!(matches!(
+            carmack_expand(&far),
+            Err(WolfAssetError::BadBackReference { offset: 4 })
+        ));
+        // copy longer than the announced length
+        let overrun = [4u8, 0, 0x11, 0x22, 3, NEAR, 1];
+        assert!(matches!(
+            carmack_expand(&overrun),
+            Err(WolfAssetError::DecompressionOverrun { offset: 4 })
+        ));
+        // stream ends inside an escaped literal
+        let truncated = [2u8, 0, 0, NEAR];
+        assert!(matches!(
+            carmack_expand(&truncated),
+            Err(WolfAssetError::TruncatedChunk { offset: 4 })
+        ));
+    }
+
+    #[test]
+    fn rlew_overrun_test() {
+        let data = [4u16, 0xABCD, 3, 7];
+        assert!(matches!(
+            rlew_expand(&data, 0xABCD),
+            Err(WolfAssetError::DecompressionOverrun { offset: 2 })
+        ));
+    }
+
+    #[test]
+    fn write_maps_round_trip_test() {
+        let plane = synthetic_plane();
+        let objects: Vec<u16> = (0..64 * 64).map(|i| if i % 97 == 0 { 23 } else { 0 }).collect();
+        let mut name = [0u8; 16];
+        name[..8].copy_from_slice(b"Wolf1 M1");
+        let level = |name: [u8; 16]| WolfLevel {
+            width: 64,
+            height: 64,
+            name: name,
+            ..Default::default()
+        };
+        let levels = vec![
+            (level(name), [plane.clone(), objects.clone(), vec![0u16; 64 * 64]]),
+            (level([0u8; 16]), [objects.clone(), plane.clone(), plane.clone()]),
+        ];
+
+        let mut map_head = Vec::new();
+        let mut game_maps = Vec::new();
+        let written = write_maps(0xABCD, &levels, &mut map_head, &mut game_maps).unwrap();
+        assert_eq!(map_head.len(), 402);
+        assert_eq!(&game_maps[0..8], b"TED5v1.0");
+
+        let atlas = parse_atlas(&map_head).unwrap();
+        assert_eq!(atlas.map_offset, written.map_offset);
+        assert_eq!(atlas.map_offset[2], 0);
+
+        let mut file = Cursor::new(game_maps);
+        for (index, (header, planes)) in levels.iter().enumerate() {
+            let read = read_level(&atlas, &mut file, 10, 1, index as i32 + 1).unwrap();
+            assert_eq!(read.width, 64);
+            assert_eq!(read.height, 64);
+            assert_eq!(read.name, header.name);
+            for (plane_index, plane) in planes.iter().enumerate() {
+                assert_eq!(&read_map(&atlas, &read, &mut file, plane_index).unwrap(), plane);
+            }
+        }
+    }
+
+    #[test]
+    fn write_maps_plane_size_test() {
+        let levels = vec![(
+            WolfLevel {
+                width: 64,
+                height: 64,
+                ..Default::default()
+            },
+            [vec![0u16; 64 * 64], vec![0u16; 10], vec![0u16; 64 * 64]],
+        )];
+        assert!(matches!(
+            write_maps(0xABCD, &levels, &mut Vec::new(), &mut Vec::new()),
+            Err(WolfAssetError::PlaneSizeMismatch {
+                level: 0,
+                plane: 1,
+                expected: 4096,
+                actual: 10
+            })
+        ));
+    }
+
+    #[test]
+    fn write_maps_plane_too_large_test() {
+        let tiles = 256 * 256;
+        let levels = vec![(
+            WolfLevel {
+                width: 256,
+                height: 256,
+                ..Default::default()
+            },
+            [vec![0u16; tiles], vec![0u16; tiles], vec![0u16; tiles]],
+        )];
+        assert!(matches!(
+            write_maps(0xABCD, &levels, &mut Vec::new(), &mut Vec::new()),
+            Err(WolfAssetError::PlaneTooLarge {
+                level: 0,
+                plane: 0,
+                length: 131072
+            })
+        ));
+    }
+
+    #[test]
+    fn decode_sprite_test() {
+        // columns 10 and 11: one post rows 2..5 in column 10, two posts in column 11
+        let mut chunk = Vec::new();
+        let words = |chunk: &mut Vec<u8>, words: &[u16]| {
+            words
+                .iter()
+                .for_each(|w| chunk.extend_from_slice(&w.to_le_bytes()))
+        };
+        let pixels_at = 4 + 2 * 2 + 4 * 2 + 7 * 2;
+        words(&mut chunk, &[10, 11, 8, 16]);
+        // column 10
+        words(&mut chunk, &[5 * 2, (pixels_at - 2) as u16, 2 * 2, 0]);
+        // column 11, rows 0..1 and 62..64
+        words(&mut chunk, &[2, (pixels_at + 3) as u16, 0, 64 * 2]);
+        words(&mut chunk, &[(pixels_at as i16 + 4 - 62) as u16, 62 * 2, 0]);
+        assert_eq!(chunk.len(), pixels_at);
+        chunk.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+
+        let (pixels, mask) = decode_sprite(&chunk).unwrap();
+        assert_eq!(mask.iter().filter(|&&m| m).count(), 6);
+        let at = |x: usize, y: usize| y * 64 + x;
+        assert_eq!(
+            [pixels[at(10, 2)], pixels[at(10, 3)], pixels[at(10, 4)]],
+            [1, 2, 3]
+        );
+        assert!(mask[at(10, 2)] && !mask[at(10, 5)] && !mask[at(10, 1)]);
+        assert_eq!(pixels[at(11, 0)], 4);
+        assert_eq!([pixels[at(11, 62)], pixels[at(11, 63)]], [5, 6]);
+        assert!(!mask[at(12, 2)]);
+
+        // post pointing past the end of the page
+        let last = chunk.len() - 6;
+        chunk.truncate(last + 2);
+        assert!(matches!(
+            decode_sprite(&chunk),
+            Err(WolfAssetError::TruncatedChunk { .. })
+        ));
+    }
+
+    /// Builds a VSWAP file with the given pages, the last page is the sound info list
+    fn synthetic_vswap(sprite_start: i16, sound_start: i16, pages: &[Vec<u8>]) -> Vec<u8> {
+        let mut file = Vec::new();
+        file.extend_from_slice(&(pages.len() as i16).to_le_bytes());
+        file.extend_from_slice(&sprite_start.to_le_bytes());
+        file.extend_from_slice(&sound_start.to_le_bytes());
+        let mut offset = 6 + pages.len() * 6;
+        for page in pages {
+            file.extend_from_slice(&(offset as u32).to_le_bytes());
+            offset += page.len();
+        }
+        for page in pages {
+            file.extend_from_slice(&(page.len() as i16).to_le_bytes());
+        }
+        pages.iter().for_each(|page| file.extend_from_slice(page));
+        file
+    }
+
+    #[test]
+    fn read_sound_test() {
+        // one wall, then sound 0 spanning two pages and sound 1 in the third page
+        let mut info = Vec::new();
+        for word in &[0u16, 4096 + 100, 2, 10] {
+            info.extend_from_slice(&word.to_le_bytes());
+        }
+        let pages = vec![
+            vec![0u8; 4096],
+            vec![1u8; 4096],
+            vec![2u8; 100],
+            vec![3u8; 10],
+            info,
+        ];
+        let mut file = Cursor::new(synthetic_vswap(1, 1, &pages));
+        let header = read_vswap(&mut file).unwrap();
+
+        let sounds = read_sound_info(&mut file, &header).unwrap();
+        assert_eq!(
+            sounds,
+            vec![
+                WolfSoundInfo {
+                    start_page: 0,
+                    length: 4196
+                },
+                WolfSoundInfo {
+                    start_page: 2,
+                    length: 10
+                }
+            ]
+        );
+        let first = read_sound(&mut file, &header, &sounds[0]).unwrap();
+        assert_eq!(first.len(), 4196);
+        assert!(first[..4096].iter().all(|&s| s == 1));
+        assert!(first[4096..].iter().all(|&s| s == 2));
+        assert_eq!(read_sound(&mut file, &header, &sounds[1]).unwrap(), vec![3u8; 10]);
+
+        // longer than the remaining pages
+        let broken = WolfSoundInfo {
+            start_page: 2,
+            length: 200,
+        };
+        assert!(matches!(
+            read_sound(&mut file, &header, &broken),
+            Err(WolfAssetError::TruncatedChunk { .. })
+        ));
+
+        let mut wav = Vec::new();
+        write_wav_u8(&mut wav, &first, 7042).unwrap();
+        assert_eq!(&wav[0..4], b"RIFF");
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(u32::from_le_bytes([wav[24], wav[25], wav[26], wav[27]]), 7042);
+        assert_eq!(u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]), 4196);
+        assert_eq!(wav.len(), 44 + 4196);
+
+        // an odd sample count gets a pad byte, counted by RIFF but not by data
+        let mut wav = Vec::new();
+        write_wav_u8(&mut wav, &[3u8; 10][..9], 7042).unwrap();
+        let riff_size = u32::from_le_bytes([wav[4], wav[5], wav[6], wav[7]]);
+        assert_eq!(u32::from_le_bytes([wav[40], wav[41], wav[42], wav[43]]), 9);
+        assert_eq!(wav.len(), riff_size as usize + 8);
+        assert_eq!(wav.len(), 44 + 10);
+    }
 }